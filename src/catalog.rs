@@ -0,0 +1,358 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::models::Tweet;
+
+/// 数据库结构版本，保存在 PRAGMA user_version 中，结构变化时用于迁移
+const SCHEMA_VERSION: i64 = 2;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS authors (
+    id TEXT PRIMARY KEY,
+    screen_name TEXT,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS tweets (
+    id TEXT PRIMARY KEY,
+    author_id TEXT,
+    screen_name TEXT,
+    text TEXT,
+    created_at TEXT,
+    updated_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS tweets_author_id ON tweets (author_id);
+
+-- 各下载来源（点赞、书签等）中已完成的推文，sort_index 为推文在时间线中的排序键
+CREATE TABLE IF NOT EXISTS downloads (
+    list TEXT NOT NULL,
+    tweet_id TEXT NOT NULL,
+    sort_index TEXT,
+    downloaded_at TEXT NOT NULL,
+    PRIMARY KEY (list, tweet_id)
+);
+
+CREATE TABLE IF NOT EXISTS media (
+    tweet_id TEXT NOT NULL,
+    media_id TEXT NOT NULL,
+    url TEXT NOT NULL,
+    path TEXT,
+    size INTEGER,
+    sha256 TEXT,
+    status TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    -- 图片实际下载的尺寸，以及尝试过的最大尺寸（较大尺寸返回 404 时与前者不同）
+    photo_size TEXT,
+    photo_size_tried TEXT,
+    PRIMARY KEY (tweet_id, media_id)
+);
+
+-- 已导入的旧版文本记录，每个只在第一次运行时导入
+CREATE TABLE IF NOT EXISTS imports (
+    name TEXT PRIMARY KEY,
+    file TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    imported_at TEXT NOT NULL
+);
+";
+
+/// 媒体文件的下载状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaStatus {
+    Done,
+    /// 媒体已不存在（404），不再重试
+    Gone,
+    Failed,
+}
+
+impl MediaStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MediaStatus::Done => "done",
+            MediaStatus::Gone => "gone",
+            MediaStatus::Failed => "failed",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "done" => Some(MediaStatus::Done),
+            "gone" => Some(MediaStatus::Gone),
+            "failed" => Some(MediaStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// media 表中的一行
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaFile {
+    pub tweet_id: String,
+    pub media_id: String,
+    pub url: String,
+    /// 下载后的文件路径，未成功下载时为 None
+    pub path: Option<String>,
+    pub size: Option<u64>,
+    pub sha256: Option<String>,
+    pub status: MediaStatus,
+    /// 图片实际下载的尺寸，非图片或没有记录时为 None
+    pub photo_size: Option<String>,
+    /// 下载图片时尝试过的最大尺寸
+    pub photo_size_tried: Option<String>,
+}
+
+/// 已下载推文、媒体和作者的目录，保存在 CATALOG_DB 指定的 SQLite 数据库中
+pub struct Catalog {
+    conn: Mutex<Connection>,
+}
+
+impl Catalog {
+    pub fn open(filename: &str) -> Result<Self> {
+        if let Some(parent) = Path::new(filename).parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let conn =
+            Connection::open(filename).with_context(|| format!("无法打开数据库: {}", filename))?;
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(anyhow!(
+                "数据库 {} 由更新版本的程序创建，请升级后再使用",
+                filename
+            ));
+        }
+        if version == 1 {
+            // 版本 1 的 media 表没有图片尺寸列
+            conn.execute_batch(
+                "ALTER TABLE media ADD COLUMN photo_size TEXT;
+                 ALTER TABLE media ADD COLUMN photo_size_tried TEXT;",
+            )
+            .with_context(|| format!("无法升级数据库: {}", filename))?;
+        }
+        conn.execute_batch(SCHEMA)
+            .with_context(|| format!("无法初始化数据库: {}", filename))?;
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+
+        Ok(Catalog {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// 导入旧版下载记录文件（每行一个推文 ID），每个来源只导入一次，返回导入的条数
+    pub fn import_downloaded_ids(&self, list: &str, filename: &str) -> Result<usize> {
+        self.import_once(&format!("downloads:{}", list), filename, |conn, line, now| {
+            conn.execute(
+                "INSERT OR IGNORE INTO downloads (list, tweet_id, downloaded_at) VALUES (?1, ?2, ?3)",
+                params![list, line, now],
+            )
+        })
+    }
+
+    /// 导入旧版媒体下载记录（每行为 `<推文ID>_<媒体ID> <done|gone>`），只导入一次，返回导入的条数
+    pub fn import_media_records(&self, filename: &str) -> Result<usize> {
+        self.import_once("media", filename, |conn, line, now| {
+            let Some((tweet_id, media_id, status)) = line
+                .split_once(' ')
+                .and_then(|(key, status)| Some((key.split_once('_')?, status.trim())))
+                .and_then(|((tweet_id, media_id), status)| {
+                    Some((tweet_id, media_id, MediaStatus::parse(status)?))
+                })
+            else {
+                return Ok(0);
+            };
+            // 同一媒体以最后一行为准
+            conn.execute(
+                "INSERT OR REPLACE INTO media (tweet_id, media_id, url, status, updated_at)
+                 VALUES (?1, ?2, '', ?3, ?4)",
+                params![tweet_id, media_id, status.as_str(), now],
+            )
+        })
+    }
+
+    /// 在一个事务中逐行导入文件，并在 imports 表中记录，之后不再导入。
+    /// 文件不存在时不记录，之后出现该文件时仍会导入
+    fn import_once(
+        &self,
+        name: &str,
+        filename: &str,
+        insert: impl Fn(&Connection, &str, &str) -> rusqlite::Result<usize>,
+    ) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let imported: Option<i64> = conn
+            .query_row(
+                "SELECT count FROM imports WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if imported.is_some() {
+            return Ok(0);
+        }
+
+        if !Path::new(filename).exists() {
+            return Ok(0);
+        }
+        let content =
+            fs::read_to_string(filename).with_context(|| format!("无法读取文件: {}", filename))?;
+
+        let now = now();
+        let tx = conn.transaction()?;
+        let mut count = 0;
+        for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            count += insert(&tx, line, &now)?;
+        }
+        tx.execute(
+            "INSERT INTO imports (name, file, count, imported_at) VALUES (?1, ?2, ?3, ?4)",
+            params![name, filename, count as i64, now],
+        )?;
+        tx.commit()
+            .with_context(|| format!("无法导入记录文件: {}", filename))?;
+        Ok(count)
+    }
+
+    /// 某个来源中已下载的推文 ID
+    pub fn downloaded_ids(&self, list: &str) -> Result<HashSet<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT tweet_id FROM downloads WHERE list = ?1")?;
+        let ids = stmt
+            .query_map(params![list], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(ids)
+    }
+
+    pub fn save_download(
+        &self,
+        list: &str,
+        tweet_id: &str,
+        sort_index: Option<u128>,
+    ) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO downloads (list, tweet_id, sort_index, downloaded_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (list, tweet_id) DO UPDATE SET
+                 sort_index = COALESCE(excluded.sort_index, sort_index)",
+            params![list, tweet_id, sort_index.map(|s| s.to_string()), now()],
+        )?;
+        Ok(())
+    }
+
+    /// 保存推文及其作者，已有的记录会被更新
+    pub fn save_tweet(&self, tweet: &Tweet) -> Result<()> {
+        let now = now();
+        let created_at = tweet.created_at().map(|dt| dt.to_rfc3339());
+
+        let conn = self.conn.lock().unwrap();
+        if let Some(author_id) = tweet.author_id() {
+            conn.execute(
+                "INSERT INTO authors (id, screen_name, updated_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (id) DO UPDATE SET
+                     screen_name = COALESCE(excluded.screen_name, screen_name),
+                     updated_at = excluded.updated_at",
+                params![author_id, tweet.screen_name(), now],
+            )?;
+        }
+        conn.execute(
+            "INSERT INTO tweets (id, author_id, screen_name, text, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (id) DO UPDATE SET
+                 author_id = COALESCE(excluded.author_id, author_id),
+                 screen_name = COALESCE(excluded.screen_name, screen_name),
+                 text = COALESCE(excluded.text, text),
+                 created_at = COALESCE(excluded.created_at, created_at),
+                 updated_at = excluded.updated_at",
+            params![
+                tweet.rest_id,
+                tweet.author_id(),
+                tweet.screen_name(),
+                tweet.text(),
+                created_at,
+                now
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn media(&self, tweet_id: &str, media_id: &str) -> Result<Option<MediaFile>> {
+        let conn = self.conn.lock().unwrap();
+        let media = conn
+            .query_row(
+                "SELECT url, path, size, sha256, status, photo_size, photo_size_tried FROM media
+                 WHERE tweet_id = ?1 AND media_id = ?2",
+                params![tweet_id, media_id],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, Option<i64>>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, String>(4)?,
+                        row.get::<_, Option<String>>(5)?,
+                        row.get::<_, Option<String>>(6)?,
+                    ))
+                },
+            )
+            .optional()?;
+
+        Ok(media.and_then(
+            |(url, path, size, sha256, status, photo_size, photo_size_tried)| {
+                Some(MediaFile {
+                    tweet_id: tweet_id.to_string(),
+                    media_id: media_id.to_string(),
+                    url,
+                    path,
+                    size: size.map(|s| s as u64),
+                    sha256,
+                    status: MediaStatus::parse(&status)?,
+                    photo_size,
+                    photo_size_tried,
+                })
+            },
+        ))
+    }
+
+    /// 保存媒体的下载结果。已完成或已不存在的媒体不会被之后的失败结果覆盖，保留原有的路径和哈希；
+    /// 没有图片尺寸时（如跳过已存在的文件）保留原有的尺寸记录
+    pub fn save_media(&self, media: &MediaFile) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO media (tweet_id, media_id, url, path, size, sha256, status, updated_at,
+                                photo_size, photo_size_tried)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+             ON CONFLICT (tweet_id, media_id) DO UPDATE SET
+                 url = excluded.url,
+                 path = excluded.path,
+                 size = excluded.size,
+                 sha256 = excluded.sha256,
+                 status = excluded.status,
+                 updated_at = excluded.updated_at,
+                 photo_size = COALESCE(excluded.photo_size, photo_size),
+                 photo_size_tried = COALESCE(excluded.photo_size_tried, photo_size_tried)
+             WHERE excluded.status != 'failed' OR status = 'failed'",
+            params![
+                media.tweet_id,
+                media.media_id,
+                media.url,
+                media.path,
+                media.size.map(|s| s as i64),
+                media.sha256,
+                media.status.as_str(),
+                now(),
+                media.photo_size,
+                media.photo_size_tried
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+fn now() -> String {
+    Local::now().to_rfc3339()
+}
+
+#[cfg(test)]
+#[path = "catalog_test.rs"]
+mod tests;
@@ -0,0 +1,199 @@
+use super::*;
+use serde_json::json;
+
+fn temp_file(name: &str, content: &str) -> String {
+    let path = std::env::temp_dir().join(format!(
+        "x_likes_downloader_{}_{}",
+        std::process::id(),
+        name
+    ));
+    fs::write(&path, content).unwrap();
+    path.to_string_lossy().to_string()
+}
+
+#[test]
+fn test_import_legacy_records_once() {
+    let catalog = Catalog::open(":memory:").unwrap();
+    let ids = temp_file("catalog_ids.txt", "1\n2\n\n2\n");
+    let media = temp_file(
+        "catalog_media.txt",
+        "1_11 done\n1_12 gone\n1_11 gone\nbad\n",
+    );
+
+    assert_eq!(catalog.import_downloaded_ids("likes", &ids).unwrap(), 2);
+    assert_eq!(
+        catalog.downloaded_ids("likes").unwrap(),
+        HashSet::from(["1".to_string(), "2".to_string()])
+    );
+    assert!(catalog.downloaded_ids("bookmarks").unwrap().is_empty());
+
+    // 已导入的来源不再重复导入，即使文件内容有变化
+    fs::write(&ids, "3\n").unwrap();
+    assert_eq!(catalog.import_downloaded_ids("likes", &ids).unwrap(), 0);
+    assert!(!catalog.downloaded_ids("likes").unwrap().contains("3"));
+
+    // 媒体记录以最后一行为准
+    assert_eq!(catalog.import_media_records(&media).unwrap(), 3);
+    let status = |media_id| catalog.media("1", media_id).unwrap().map(|m| m.status);
+    assert_eq!(status("11"), Some(MediaStatus::Gone));
+    assert_eq!(status("12"), Some(MediaStatus::Gone));
+    assert_eq!(status("13"), None);
+
+    fs::remove_file(ids).unwrap();
+    fs::remove_file(media).unwrap();
+}
+
+#[test]
+fn test_missing_legacy_file_is_imported_later() {
+    let catalog = Catalog::open(":memory:").unwrap();
+    let ids = std::env::temp_dir()
+        .join(format!(
+            "x_likes_downloader_{}_catalog_later.txt",
+            std::process::id()
+        ))
+        .to_string_lossy()
+        .to_string();
+
+    // 文件不存在时不记录导入，之后出现的文件仍会导入
+    assert_eq!(catalog.import_downloaded_ids("likes", &ids).unwrap(), 0);
+    fs::write(&ids, "5\n").unwrap();
+    assert_eq!(catalog.import_downloaded_ids("likes", &ids).unwrap(), 1);
+    assert!(catalog.downloaded_ids("likes").unwrap().contains("5"));
+
+    fs::remove_file(ids).unwrap();
+}
+
+#[test]
+fn test_save_tweet_author_and_media() {
+    let catalog = Catalog::open(":memory:").unwrap();
+    let tweet: Tweet = serde_json::from_value(json!({
+        "rest_id": "100",
+        "core": { "user_results": { "result": {
+            "rest_id": "42",
+            "core": { "screen_name": "alice" }
+        } } },
+        "legacy": {
+            "created_at": "Thu Apr 06 15:24:15 +0000 2017",
+            "full_text": "截断的正文…"
+        },
+        "note_tweet": { "note_tweet_results": { "result": { "text": "完整的长推文正文" } } }
+    }))
+    .unwrap();
+    catalog.save_tweet(&tweet).unwrap();
+    catalog
+        .save_download("likes", "100", Some(1_800_000))
+        .unwrap();
+
+    let conn = catalog.conn.lock().unwrap();
+    let row: (String, String, String, String, String) = conn
+        .query_row(
+            "SELECT t.author_id, a.screen_name, t.text, t.created_at, d.sort_index
+             FROM tweets t JOIN authors a ON a.id = t.author_id
+             JOIN downloads d ON d.tweet_id = t.id WHERE t.id = '100'",
+            [],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            },
+        )
+        .unwrap();
+    drop(conn);
+    assert_eq!(
+        row,
+        (
+            "42".to_string(),
+            "alice".to_string(),
+            "完整的长推文正文".to_string(),
+            "2017-04-06T15:24:15+00:00".to_string(),
+            "1800000".to_string()
+        )
+    );
+
+    let file = MediaFile {
+        tweet_id: "100".to_string(),
+        media_id: "7".to_string(),
+        url: "https://pbs.twimg.com/media/a.jpg".to_string(),
+        path: Some("data/downloads/alice_100_a.jpg".to_string()),
+        size: Some(1024),
+        sha256: Some("abc".to_string()),
+        status: MediaStatus::Done,
+        photo_size: Some("4096x4096".to_string()),
+        photo_size_tried: Some("orig".to_string()),
+    };
+    catalog.save_media(&file).unwrap();
+    assert_eq!(catalog.media("100", "7").unwrap(), Some(file.clone()));
+
+    // 之后的失败结果不覆盖已完成的记录
+    catalog
+        .save_media(&MediaFile {
+            path: None,
+            size: None,
+            sha256: None,
+            status: MediaStatus::Failed,
+            ..file.clone()
+        })
+        .unwrap();
+    assert_eq!(catalog.media("100", "7").unwrap(), Some(file.clone()));
+
+    // 失败的记录可以被之后的成功结果覆盖
+    let failed = MediaFile {
+        media_id: "8".to_string(),
+        path: None,
+        size: None,
+        sha256: None,
+        status: MediaStatus::Failed,
+        ..file.clone()
+    };
+    catalog.save_media(&failed).unwrap();
+    assert_eq!(catalog.media("100", "8").unwrap(), Some(failed));
+    let done = MediaFile {
+        media_id: "8".to_string(),
+        ..file
+    };
+    catalog.save_media(&done).unwrap();
+    assert_eq!(catalog.media("100", "8").unwrap(), Some(done.clone()));
+
+    // 跳过已存在的文件时没有尺寸信息，保留原有的尺寸记录
+    catalog
+        .save_media(&MediaFile {
+            photo_size: None,
+            photo_size_tried: None,
+            ..done.clone()
+        })
+        .unwrap();
+    assert_eq!(catalog.media("100", "8").unwrap(), Some(done));
+}
+
+#[test]
+fn test_open_upgrades_version_1_database() {
+    let filename = std::env::temp_dir()
+        .join(format!("x_likes_downloader_{}_v1.db", std::process::id()))
+        .to_string_lossy()
+        .to_string();
+    fs::remove_file(&filename).ok();
+    {
+        let conn = Connection::open(&filename).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE media (
+                 tweet_id TEXT NOT NULL, media_id TEXT NOT NULL, url TEXT NOT NULL,
+                 path TEXT, size INTEGER, sha256 TEXT, status TEXT NOT NULL,
+                 updated_at TEXT NOT NULL, PRIMARY KEY (tweet_id, media_id));
+             INSERT INTO media (tweet_id, media_id, url, status, updated_at)
+                 VALUES ('1', '2', '', 'done', '');
+             PRAGMA user_version = 1;",
+        )
+        .unwrap();
+    }
+
+    let catalog = Catalog::open(&filename).unwrap();
+    let media = catalog.media("1", "2").unwrap().unwrap();
+    assert_eq!(media.status, MediaStatus::Done);
+    assert_eq!(media.photo_size, None);
+    drop(catalog);
+    fs::remove_file(&filename).unwrap();
+}
@@ -1,11 +1,15 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::collections::HashMap;
 use std::env;
+use std::fmt;
 use std::fs;
 use std::path::Path;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::proxy::ProxySettings;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub struct Config {
     // 用户认证信息
     pub user_id: String,
@@ -22,16 +26,54 @@ pub struct Config {
     pub all: bool,
     pub download_dir: String,
     pub download_record: String,
+    pub bookmarks_download_record: String,
+    pub user_media_download_record: String,
+    pub tweets_download_record: String,
+    /// 已下载推文、媒体和作者的 SQLite 数据库
+    pub catalog_db: String,
+    /// 旧版按媒体记录的下载结果，第一次运行时导入数据库
+    pub media_download_record: String,
+    pub follow_quotes_and_retweets: bool,
+    /// 是否在每个媒体文件旁写入 `<文件名>.json` 元数据文件
+    pub metadata_sidecar: bool,
+    /// 是否将推文元数据写入图片的 XMP 和 MP4 的元数据
+    pub embed_metadata: bool,
+    pub referenced_tweets_record: String,
+    pub unavailable_tweets_record: String,
     pub file_format: String,
+    pub concurrency: usize,
+    pub per_host_concurrency: usize,
+    pub incremental: bool,
+    pub incremental_stop_after: usize,
+    pub crawl_state_file: String,
+    pub checkpoint_file: String,
+    pub gif_convert_command: String,
+    pub gif_convert_format: String,
+    pub photo_quality: String,
+    pub photo_format: String,
+    pub photo_variants_file: String,
+    pub video_quality: String,
+    pub hls_merge_command: String,
+
+    // 代理配置
+    #[serde(flatten)]
+    pub proxy: ProxySettings,
 
     // 整理配置
     pub auto_organize: bool,
     pub target_dir: String,
 
     // API配置
+    pub max_retries: u32,
     pub likes_api_url: String,
     pub likes_features: String,
     pub likes_fieldtoggles: String,
+    pub bookmarks_api_url: String,
+    pub bookmarks_features: String,
+    pub user_media_api_url: String,
+    pub user_by_screen_name_api_url: String,
+    pub user_features: String,
+    pub user_fieldtoggles: String,
     pub tweet_detail_api_url: String,
     pub tweet_features: String,
     pub tweet_fieldtoggles: String,
@@ -39,13 +81,74 @@ pub struct Config {
     // Mock配置
     pub mock_mode: bool,
     pub mock_liked_tweets_file: String,
+    pub mock_bookmarks_file: String,
+    pub mock_user_media_file: String,
+    pub mock_tweet_detail_file: String,
+    pub mock_media_dir: String,
+}
+
+/// 图片尺寸，从大到小排列，请求的尺寸不存在时依次尝试更小的尺寸
+pub const PHOTO_SIZES: [&str; 5] = ["orig", "4096x4096", "large", "medium", "small"];
+
+/// Likes、UserMedia 请求使用的 features，Bookmarks 在此基础上增加自己的开关
+const TIMELINE_FEATURES: &str = r#"{"rweb_video_screen_enabled":false,"profile_label_improvements_pcf_label_in_post_enabled":true,"rweb_tipjar_consumption_enabled":true,"responsive_web_graphql_exclude_directive_enabled":true,"verified_phone_label_enabled":false,"creator_subscriptions_tweet_preview_api_enabled":true,"responsive_web_graphql_timeline_navigation_enabled":true,"responsive_web_graphql_skip_user_profile_image_extensions_enabled":false,"premium_content_api_read_enabled":false,"communities_web_enable_tweet_community_results_fetch":true,"c9s_tweet_anatomy_moderator_badge_enabled":true,"responsive_web_grok_analyze_button_fetch_trends_enabled":false,"responsive_web_grok_analyze_post_followups_enabled":true,"responsive_web_jetfuel_frame":false,"responsive_web_grok_share_attachment_enabled":true,"articles_preview_enabled":true,"responsive_web_edit_tweet_api_enabled":true,"graphql_is_translatable_rweb_tweet_is_translatable_enabled":true,"view_counts_everywhere_api_enabled":true,"longform_notetweets_consumption_enabled":true,"responsive_web_twitter_article_tweet_consumption_enabled":true,"tweet_awards_web_tipping_enabled":false,"responsive_web_grok_analysis_button_from_backend":false,"creator_subscriptions_quote_tweet_preview_enabled":false,"freedom_of_speech_not_reach_fetch_enabled":true,"standardized_nudges_misinfo":true,"tweet_with_visibility_results_prefer_gql_limited_actions_policy_enabled":true,"rweb_video_timestamps_enabled":true,"longform_notetweets_rich_text_read_enabled":true,"longform_notetweets_inline_media_enabled":true,"responsive_web_grok_image_annotation_enabled":false,"responsive_web_enhance_cards_enabled":false}"#;
+
+/// 在 TIMELINE_FEATURES 的基础上设置额外的开关
+fn timeline_features(extra: &[(&str, bool)]) -> String {
+    let mut features: Map<String, Value> =
+        serde_json::from_str(TIMELINE_FEATURES).expect("TIMELINE_FEATURES 不是合法的 JSON");
+    for (name, enabled) in extra {
+        features.insert(name.to_string(), Value::Bool(*enabled));
+    }
+    Value::Object(features).to_string()
+}
+
+/// 输出时代替敏感值的占位符
+pub const REDACTED: &str = "<已隐藏>";
+
+/// 调试输出时需要隐藏的令牌字段
+const SECRET_FIELDS: [&str; 5] = [
+    "bearer_token",
+    "auth_token",
+    "ct0",
+    "personalization_id",
+    "proxy_password",
+];
+
+/// 可能在地址中带有用户名和密码的代理字段
+const PROXY_FIELDS: [&str; 3] = ["proxy", "https_proxy", "socks5"];
+
+/// 调试输出时隐藏令牌，避免写入终端或日志
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut value = serde_json::to_value(self).map_err(|_| fmt::Error)?;
+        for field in SECRET_FIELDS {
+            if let Some(secret) = value.get_mut(field) {
+                *secret = REDACTED.into();
+            }
+        }
+        for field in PROXY_FIELDS {
+            if let Some(url) = value
+                .get_mut(field)
+                .filter(|url| url.to_string().contains('@'))
+            {
+                *url = REDACTED.into();
+            }
+        }
+        write!(f, "Config {}", value)
+    }
 }
 
 impl Config {
     pub fn load() -> Result<Self> {
         dotenv::dotenv().ok();
 
-        let private_tokens = Self::load_private_tokens("data/private_tokens.env")?;
+        // Mock 模式不访问 X，不需要令牌文件
+        let mock_mode = env::var("MOCK_MODE")
+            .unwrap_or_else(|_| "False".to_string())
+            .to_lowercase()
+            == "true";
+        let private_tokens = Self::load_private_tokens("data/private_tokens.env", !mock_mode)?;
 
         Ok(Config {
             // 从private_tokens加载
@@ -63,22 +166,95 @@ impl Config {
             all: env::var("ALL").unwrap_or_else(|_| "False".to_string()).to_lowercase() == "true",
             download_dir: env::var("DOWNLOAD_DIR").unwrap_or_else(|_| "data/downloads".to_string()),
             download_record: env::var("DOWNLOAD_RECORD").unwrap_or_else(|_| "data/downloaded_tweet_ids.txt".to_string()),
+            bookmarks_download_record: env::var("BOOKMARKS_DOWNLOAD_RECORD").unwrap_or_else(|_| "data/downloaded_bookmark_ids.txt".to_string()),
+            user_media_download_record: env::var("USER_MEDIA_DOWNLOAD_RECORD").unwrap_or_else(|_| "data/downloaded_user_media_ids.txt".to_string()),
+            tweets_download_record: env::var("TWEETS_DOWNLOAD_RECORD").unwrap_or_else(|_| "data/downloaded_single_tweet_ids.txt".to_string()),
+            catalog_db: env::var("CATALOG_DB").unwrap_or_else(|_| "data/catalog.db".to_string()),
+            media_download_record: env::var("MEDIA_DOWNLOAD_RECORD").unwrap_or_else(|_| "data/downloaded_media.txt".to_string()),
+            follow_quotes_and_retweets: env::var("FOLLOW_QUOTES_AND_RETWEETS").unwrap_or_else(|_| "False".to_string()).to_lowercase() == "true",
+            metadata_sidecar: env::var("METADATA_SIDECAR").unwrap_or_else(|_| "False".to_string()).to_lowercase() == "true",
+            embed_metadata: env::var("EMBED_METADATA").unwrap_or_else(|_| "False".to_string()).to_lowercase() == "true",
+            referenced_tweets_record: env::var("REFERENCED_TWEETS_RECORD").unwrap_or_else(|_| "data/referenced_tweets.txt".to_string()),
+            unavailable_tweets_record: env::var("UNAVAILABLE_TWEETS_RECORD").unwrap_or_else(|_| "data/unavailable_tweets.txt".to_string()),
             file_format: env::var("FILE_FORMAT").unwrap_or_else(|_| "{USERNAME} {ID}".to_string()),
+            concurrency: env::var("CONCURRENCY").unwrap_or_else(|_| "4".to_string()).parse().context("CONCURRENCY 必须是正整数")?,
+            per_host_concurrency: env::var("PER_HOST_CONCURRENCY").unwrap_or_else(|_| "4".to_string()).parse().context("PER_HOST_CONCURRENCY 必须是正整数")?,
+            incremental: env::var("INCREMENTAL").unwrap_or_else(|_| "False".to_string()).to_lowercase() == "true",
+            incremental_stop_after: env::var("INCREMENTAL_STOP_AFTER").unwrap_or_else(|_| "20".to_string()).parse().context("INCREMENTAL_STOP_AFTER 必须是非负整数")?,
+            crawl_state_file: env::var("CRAWL_STATE_FILE").unwrap_or_else(|_| "data/crawl_state.json".to_string()),
+            checkpoint_file: env::var("CHECKPOINT_FILE").unwrap_or_else(|_| "data/checkpoint.json".to_string()),
+            gif_convert_command: env::var("GIF_CONVERT_COMMAND").unwrap_or_default(),
+            gif_convert_format: Self::parse_gif_convert_format(env::var("GIF_CONVERT_FORMAT").unwrap_or_else(|_| "gif".to_string()))?,
+            photo_quality: Self::parse_photo_quality(env::var("PHOTO_QUALITY").unwrap_or_else(|_| "orig".to_string()))?,
+            photo_format: Self::parse_photo_format(env::var("PHOTO_FORMAT").unwrap_or_default())?,
+            photo_variants_file: env::var("PHOTO_VARIANTS_FILE").unwrap_or_else(|_| "data/photo_variants.json".to_string()),
+            video_quality: env::var("VIDEO_QUALITY").unwrap_or_else(|_| "highest".to_string()),
+            hls_merge_command: env::var("HLS_MERGE_COMMAND").unwrap_or_default(),
+            proxy: ProxySettings::from_env(),
             auto_organize: env::var("AUTO_ORGANIZE").unwrap_or_else(|_| "False".to_string()).to_lowercase() == "true",
             target_dir: env::var("TARGET_DIR").unwrap_or_else(|_| "data/organized".to_string()),
+            max_retries: env::var("MAX_RETRIES").unwrap_or_else(|_| "5".to_string()).parse().context("MAX_RETRIES 必须是非负整数")?,
             likes_api_url: env::var("LIKES_API_URL").unwrap_or_else(|_| "https://x.com/i/api/graphql/nWpDa3j6UoobbTNcFu_Uog/Likes".to_string()),
-            likes_features: env::var("LIKES_FEATURES").unwrap_or_else(|_| r#"{"rweb_video_screen_enabled":false,"profile_label_improvements_pcf_label_in_post_enabled":true,"rweb_tipjar_consumption_enabled":true,"responsive_web_graphql_exclude_directive_enabled":true,"verified_phone_label_enabled":false,"creator_subscriptions_tweet_preview_api_enabled":true,"responsive_web_graphql_timeline_navigation_enabled":true,"responsive_web_graphql_skip_user_profile_image_extensions_enabled":false,"premium_content_api_read_enabled":false,"communities_web_enable_tweet_community_results_fetch":true,"c9s_tweet_anatomy_moderator_badge_enabled":true,"responsive_web_grok_analyze_button_fetch_trends_enabled":false,"responsive_web_grok_analyze_post_followups_enabled":true,"responsive_web_jetfuel_frame":false,"responsive_web_grok_share_attachment_enabled":true,"articles_preview_enabled":true,"responsive_web_edit_tweet_api_enabled":true,"graphql_is_translatable_rweb_tweet_is_translatable_enabled":true,"view_counts_everywhere_api_enabled":true,"longform_notetweets_consumption_enabled":true,"responsive_web_twitter_article_tweet_consumption_enabled":true,"tweet_awards_web_tipping_enabled":false,"responsive_web_grok_analysis_button_from_backend":false,"creator_subscriptions_quote_tweet_preview_enabled":false,"freedom_of_speech_not_reach_fetch_enabled":true,"standardized_nudges_misinfo":true,"tweet_with_visibility_results_prefer_gql_limited_actions_policy_enabled":true,"rweb_video_timestamps_enabled":true,"longform_notetweets_rich_text_read_enabled":true,"longform_notetweets_inline_media_enabled":true,"responsive_web_grok_image_annotation_enabled":false,"responsive_web_enhance_cards_enabled":false}"#.to_string()),
+            likes_features: env::var("LIKES_FEATURES").unwrap_or_else(|_| TIMELINE_FEATURES.to_string()),
             likes_fieldtoggles: env::var("LIKES_FIELDTOGGLES").unwrap_or_else(|_| r#"{"withArticlePlainText":false}"#.to_string()),
+            bookmarks_api_url: env::var("BOOKMARKS_API_URL").unwrap_or_else(|_| "https://x.com/i/api/graphql/QUjXply7fA7fk05FRyajEg/Bookmarks".to_string()),
+            bookmarks_features: env::var("BOOKMARKS_FEATURES").unwrap_or_else(|_| timeline_features(&[("graphql_timeline_v2_bookmark_timeline", true)])),
+            user_media_api_url: env::var("USER_MEDIA_API_URL").unwrap_or_else(|_| "https://x.com/i/api/graphql/HaouMjBviBKKTYZGV_9qtg/UserMedia".to_string()),
+            user_by_screen_name_api_url: env::var("USER_BY_SCREEN_NAME_API_URL").unwrap_or_else(|_| "https://x.com/i/api/graphql/32pL5BWe9WKeSK1MoPvFQQ/UserByScreenName".to_string()),
+            user_features: env::var("USER_FEATURES").unwrap_or_else(|_| r#"{"hidden_profile_subscriptions_enabled":true,"profile_label_improvements_pcf_label_in_post_enabled":true,"rweb_tipjar_consumption_enabled":true,"verified_phone_label_enabled":false,"subscriptions_verification_info_is_identity_verified_enabled":true,"subscriptions_verification_info_verified_since_enabled":true,"highlights_tweets_tab_ui_enabled":true,"responsive_web_twitter_article_notes_tab_enabled":true,"subscriptions_feature_can_gift_premium":true,"creator_subscriptions_tweet_preview_api_enabled":true,"responsive_web_graphql_skip_user_profile_image_extensions_enabled":false,"responsive_web_graphql_timeline_navigation_enabled":true}"#.to_string()),
+            user_fieldtoggles: env::var("USER_FIELDTOGGLES").unwrap_or_else(|_| r#"{"withAuxiliaryUserLabels":true}"#.to_string()),
             tweet_detail_api_url: env::var("TWEET_DETAIL_API_URL").unwrap_or_else(|_| "https://x.com/i/api/graphql/_8aYOgEDz35BrBcBal1-_w/TweetDetail".to_string()),
             tweet_features: env::var("TWEET_FEATURES").unwrap_or_else(|_| r#"{"rweb_video_screen_enabled":false,"profile_label_improvements_pcf_label_in_post_enabled":true,"rweb_tipjar_consumption_enabled":true,"verified_phone_label_enabled":false,"creator_subscriptions_tweet_preview_api_enabled":true,"responsive_web_graphql_timeline_navigation_enabled":true,"responsive_web_graphql_skip_user_profile_image_extensions_enabled":false,"premium_content_api_read_enabled":false,"communities_web_enable_tweet_community_results_fetch":true,"c9s_tweet_anatomy_moderator_badge_enabled":true,"responsive_web_grok_analyze_button_fetch_trends_enabled":false,"responsive_web_grok_analyze_post_followups_enabled":true,"responsive_web_jetfuel_frame":false,"responsive_web_grok_share_attachment_enabled":true,"articles_preview_enabled":true,"responsive_web_edit_tweet_api_enabled":true,"graphql_is_translatable_rweb_tweet_is_translatable_enabled":true,"view_counts_everywhere_api_enabled":true,"longform_notetweets_consumption_enabled":true,"responsive_web_twitter_article_tweet_consumption_enabled":true,"tweet_awards_web_tipping_enabled":false,"responsive_web_grok_show_grok_translated_post":false,"responsive_web_grok_analysis_button_from_backend":false,"creator_subscriptions_quote_tweet_preview_enabled":false,"freedom_of_speech_not_reach_fetch_enabled":true,"standardized_nudges_misinfo":true,"tweet_with_visibility_results_prefer_gql_limited_actions_policy_enabled":true,"longform_notetweets_rich_text_read_enabled":true,"longform_notetweets_inline_media_enabled":true,"responsive_web_grok_image_annotation_enabled":true,"responsive_web_enhance_cards_enabled":false}"#.to_string()),
             tweet_fieldtoggles: env::var("TWEET_FIELDTOGGLES").unwrap_or_else(|_| r#"{"withArticleRichContentState":true,"withArticlePlainText":false,"withGrokAnalyze":false,"withDisallowedReplyControls":false}"#.to_string()),
-            mock_mode: env::var("MOCK_MODE").unwrap_or_else(|_| "False".to_string()).to_lowercase() == "true",
+            mock_mode,
             mock_liked_tweets_file: env::var("MOCK_LIKED_TWEETS_FILE").unwrap_or_else(|_| "data/mock/mock_liked_tweets.json".to_string()),
+            mock_bookmarks_file: env::var("MOCK_BOOKMARKS_FILE").unwrap_or_else(|_| "data/mock/mock_bookmarks.json".to_string()),
+            mock_user_media_file: env::var("MOCK_USER_MEDIA_FILE").unwrap_or_else(|_| "data/mock/mock_user_media.json".to_string()),
+            mock_tweet_detail_file: env::var("MOCK_TWEET_DETAIL_FILE").unwrap_or_else(|_| "data/mock/mock_tweet_detail.json".to_string()),
+            mock_media_dir: env::var("MOCK_MEDIA_DIR").unwrap_or_else(|_| "data/mock/media".to_string()),
         })
     }
 
-    fn load_private_tokens(filename: &str) -> Result<HashMap<String, String>> {
+    fn parse_photo_quality(quality: String) -> Result<String> {
+        let quality = quality.trim().to_lowercase();
+        if !PHOTO_SIZES.contains(&quality.as_str()) {
+            return Err(anyhow::anyhow!(
+                "PHOTO_QUALITY 只支持 {}，当前为: {}",
+                PHOTO_SIZES.join("、"),
+                quality
+            ));
+        }
+        Ok(quality)
+    }
+
+    fn parse_photo_format(format: String) -> Result<String> {
+        let format = format.trim().to_lowercase();
+        if !format.is_empty() && !["jpg", "png", "webp"].contains(&format.as_str()) {
+            return Err(anyhow::anyhow!(
+                "PHOTO_FORMAT 只支持 jpg、png 或 webp（留空保持原格式），当前为: {}",
+                format
+            ));
+        }
+        Ok(format)
+    }
+
+    fn parse_gif_convert_format(format: String) -> Result<String> {
+        let format = format.trim().to_lowercase();
+        if format != "gif" && format != "webp" {
+            return Err(anyhow::anyhow!(
+                "GIF_CONVERT_FORMAT 只支持 gif 或 webp，当前为: {}",
+                format
+            ));
+        }
+        Ok(format)
+    }
+
+    /// 读取令牌文件，`required` 为 false 时文件不存在返回空表
+    fn load_private_tokens(filename: &str, required: bool) -> Result<HashMap<String, String>> {
         if !Path::new(filename).exists() {
+            if !required {
+                return Ok(HashMap::new());
+            }
             return Err(anyhow::anyhow!(
                 "{} 不存在，请先运行 setup 命令初始化。",
                 filename
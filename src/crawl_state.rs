@@ -0,0 +1,148 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::config::PHOTO_SIZES;
+
+/// 各时间线的抓取状态，以 JSON 保存在 CRAWL_STATE_FILE 中
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CrawlState {
+    /// 时间线键 -> 已抓取到的最新 sortIndex（字符串形式保存，避免 JSON 数字精度问题）
+    #[serde(default)]
+    newest_sort_index: HashMap<String, String>,
+}
+
+impl CrawlState {
+    pub fn load(filename: &str) -> Result<Self> {
+        if !Path::new(filename).exists() {
+            return Ok(CrawlState::default());
+        }
+
+        let content =
+            fs::read_to_string(filename).with_context(|| format!("无法读取文件: {}", filename))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("无法解析抓取状态文件: {}", filename))
+    }
+
+    pub fn save(&self, filename: &str) -> Result<()> {
+        if let Some(parent) = Path::new(filename).parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(filename, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("无法写入文件: {}", filename))
+    }
+
+    pub fn newest_sort_index(&self, key: &str) -> Option<u128> {
+        self.newest_sort_index.get(key)?.parse().ok()
+    }
+
+    /// 更新最新 sortIndex，只会向前推进
+    pub fn update_newest_sort_index(&mut self, key: &str, sort_index: u128) {
+        if self
+            .newest_sort_index(key)
+            .is_some_and(|old| old >= sort_index)
+        {
+            return;
+        }
+        self.newest_sort_index
+            .insert(key.to_string(), sort_index.to_string());
+    }
+}
+
+/// 全量抓取的分页检查点，记录最后一次成功获取的 bottom cursor 和当时的运行参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub cursor: String,
+    /// 到该检查点为止累计抓取的页数
+    pub pages: usize,
+    pub count: String,
+    pub incremental: bool,
+    pub updated_at: String,
+}
+
+/// 各时间线的检查点，以 JSON 保存在 CHECKPOINT_FILE 中
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CheckpointStore {
+    #[serde(default)]
+    checkpoints: HashMap<String, Checkpoint>,
+}
+
+impl CheckpointStore {
+    pub fn load(filename: &str) -> Result<Self> {
+        if !Path::new(filename).exists() {
+            return Ok(CheckpointStore::default());
+        }
+
+        let content =
+            fs::read_to_string(filename).with_context(|| format!("无法读取文件: {}", filename))?;
+        serde_json::from_str(&content).with_context(|| format!("无法解析检查点文件: {}", filename))
+    }
+
+    pub fn save(&self, filename: &str) -> Result<()> {
+        if let Some(parent) = Path::new(filename).parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(filename, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("无法写入文件: {}", filename))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Checkpoint> {
+        self.checkpoints.get(key)
+    }
+
+    pub fn set(&mut self, key: &str, checkpoint: Checkpoint) {
+        self.checkpoints.insert(key.to_string(), checkpoint);
+    }
+
+    /// 移除检查点，返回是否存在过
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.checkpoints.remove(key).is_some()
+    }
+}
+
+/// 记录的图片尺寸 `recorded` 是否不小于 `wanted`，或者不小于 `wanted` 的尺寸已确认不存在
+/// （`tried` 为下载时尝试过的最大尺寸）
+pub fn photo_size_at_least(recorded: &str, tried: Option<&str>, wanted: &str) -> bool {
+    let rank = |size: &str| PHOTO_SIZES.iter().position(|s| *s == size);
+    let Some(wanted) = rank(wanted) else {
+        return false;
+    };
+    std::iter::once(recorded)
+        .chain(tried)
+        .any(|size| rank(size).is_some_and(|r| r <= wanted))
+}
+
+/// 旧版本保存在 PHOTO_VARIANTS_FILE 中的图片尺寸（文件名 -> 尺寸名）。
+/// 现在尺寸记录在数据库的 media 表中，数据库中没有记录的旧文件仍按该文件判断是否需要升级
+#[derive(Debug, Default, Deserialize)]
+pub struct PhotoVariants {
+    #[serde(default)]
+    variants: HashMap<String, String>,
+    /// 较大尺寸返回 404 时记录尝试过的最大尺寸（文件名 -> 尺寸名）
+    #[serde(default)]
+    tried: HashMap<String, String>,
+}
+
+impl PhotoVariants {
+    pub fn load(filename: &str) -> Result<Self> {
+        if !Path::new(filename).exists() {
+            return Ok(PhotoVariants::default());
+        }
+
+        let content =
+            fs::read_to_string(filename).with_context(|| format!("无法读取文件: {}", filename))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("无法解析图片尺寸记录文件: {}", filename))
+    }
+
+    /// 记录的尺寸是否不小于 `size`，没有记录的文件视为默认的较小尺寸
+    pub fn is_at_least(&self, filename: &str, size: &str) -> bool {
+        self.variants.get(filename).is_some_and(|recorded| {
+            photo_size_at_least(recorded, self.tried.get(filename).map(String::as_str), size)
+        })
+    }
+}
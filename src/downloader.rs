@@ -1,37 +1,222 @@
 use anyhow::{Context, Result};
-use chrono::DateTime;
 use filetime::{set_file_times, FileTime};
+use futures_util::future::join_all;
 use futures_util::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
-use reqwest::Client;
-use serde_json::Value;
-use std::collections::HashSet;
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::{Client, StatusCode};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Semaphore;
+use tracing::{debug, error, info, warn};
 use url::Url;
 
-use crate::config::Config;
+use crate::catalog::{Catalog, MediaFile, MediaStatus};
+use crate::config::{Config, PHOTO_SIZES};
+use crate::crawl_state::{photo_size_at_least, PhotoVariants};
+use crate::embed;
+use crate::hls;
+use crate::logging;
+use crate::models::{MediaEntity, Tweet, TweetResult, TweetResults, VideoVariant};
+use crate::mux;
+use crate::rate_limit::{backoff_delay, is_retryable_status};
+use crate::sidecar::Sidecar;
+
+/// 一次下载到 .part 文件的结果
+enum PartStatus {
+    /// 文件已完整，附带文件大小和 SHA-256
+    Complete(u64, String),
+    /// 服务器返回错误状态
+    Failed(StatusCode),
+    /// 连接或传输中断，可从已下载的位置继续
+    Interrupted { progressed: bool, reason: String },
+}
+
+/// 一次媒体下载的结果
+enum DownloadStatus {
+    /// 下载完成，附带文件内容的 SHA-256
+    Done(String),
+    /// 服务器返回 404，图片可以换用更小的尺寸重试
+    NotFound,
+    Failed,
+}
+
+/// 视频清晰度策略，由 VIDEO_QUALITY 配置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VideoPolicy {
+    /// 码率最高
+    Highest,
+    /// 码率最低
+    Lowest,
+    /// 短边不超过指定像素（如 720p）中分辨率最高的
+    MaxResolution(u64),
+    /// 估算大小不超过指定 MB 中码率最高的
+    MaxSizeMb(u64),
+}
+
+impl VideoPolicy {
+    /// 解析 highest、lowest、720p、50mb 形式的配置，留空视为 highest
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim().to_lowercase();
+        let policy = match value.as_str() {
+            "" | "highest" => Some(VideoPolicy::Highest),
+            "lowest" => Some(VideoPolicy::Lowest),
+            _ => {
+                if let Some(height) = value.strip_suffix('p') {
+                    height.parse().ok().map(VideoPolicy::MaxResolution)
+                } else if let Some(size) = value.strip_suffix("mb") {
+                    size.trim().parse().ok().map(VideoPolicy::MaxSizeMb)
+                } else {
+                    None
+                }
+            }
+        };
+        policy.ok_or_else(|| {
+            anyhow::anyhow!(
+                "VIDEO_QUALITY 只支持 highest、lowest、<分辨率>p（如 720p）或 <大小>mb（如 50mb），当前为: {}",
+                value
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MediaKind {
+    Photo,
+    Video,
+    /// 动图，X 以 mp4 形式提供
+    AnimatedGif,
+    /// 只有 HLS (m3u8) 播放列表的视频，时长用于按大小上限选择清晰度
+    Hls {
+        duration_ms: Option<u64>,
+    },
+}
+
+/// 推文中的单个媒体文件
+#[derive(Debug, PartialEq)]
+struct Media {
+    /// 媒体 ID（id_str），缺失时使用 URL 中的文件名
+    id: String,
+    url: String,
+    kind: MediaKind,
+    /// 替代文本
+    alt_text: Option<String>,
+}
+
+/// 待下载的媒体及其所属推文的信息
+struct MediaJob {
+    media: Media,
+    /// 媒体所属推文的 ID（引用和转推时为原推文）
+    tweet_id: String,
+    /// 文件名前缀
+    prefix: String,
+    /// 推文发布时间，用于设置文件时间
+    timestamp: Option<i64>,
+    /// 推文元数据，开启 METADATA_SIDECAR 或 EMBED_METADATA 时才生成
+    metadata: Option<Sidecar>,
+}
+
+/// 单个媒体文件的处理结果
+enum MediaOutcome {
+    /// 文件已存在或已有下载记录，跳过。文件已存在时为文件路径
+    Skipped(Option<PathBuf>),
+    /// 下载完成
+    Downloaded {
+        path: PathBuf,
+        /// 文件与下载内容一致时为下载过程中计算的 SHA-256，转换或写入元数据后为 None
+        sha256: Option<String>,
+        /// 图片的（实际下载的尺寸，尝试过的最大尺寸）
+        photo_size: Option<(&'static str, &'static str)>,
+    },
+    /// 媒体已不存在（404），不再重试
+    Gone,
+    Failed,
+}
 
 pub struct Downloader {
     client: Client,
     config: Config,
-    downloaded_ids: HashSet<String>,
+    /// SQLite 的读写是同步的，在阻塞线程池中通过 `with_catalog` 访问
+    catalog: Arc<Catalog>,
+    /// 数据库中的下载来源，如 likes、bookmarks
+    list: String,
+    /// 该来源已下载的推文 ID，写入数据库成功后才加入
+    downloaded_ids: Mutex<HashSet<String>>,
+    /// 全局下载并发数
+    download_slots: Semaphore,
+    /// 每个主机的下载并发数
+    host_slots: Mutex<HashMap<String, Arc<Semaphore>>>,
+    /// 已记录为不可用的推文 ID
+    unavailable_ids: Mutex<HashSet<String>>,
+    /// 引用和转推记录中已有的行
+    references: Mutex<HashSet<String>>,
+    /// 旧版本的图片尺寸记录，只读，数据库中没有尺寸记录时使用
+    photo_variants: PhotoVariants,
+    video_policy: VideoPolicy,
+    overall: ProgressBar,
 }
 
 impl Downloader {
-    pub fn new(config: Config) -> Result<Self> {
+    /// `list` 为数据库中的下载来源，`record_file` 为该来源的旧版下载记录，第一次运行时导入数据库
+    pub fn new(config: Config, list: &str, record_file: &str) -> Result<Self> {
         let client_builder = reqwest::Client::builder().timeout(Duration::from_secs(30));
 
-        let client = client_builder.build()?;
-        let downloaded_ids = Self::load_downloaded_ids(&config.download_record)?;
+        let client = config.proxy.apply(client_builder)?.build()?;
+        let catalog = Catalog::open(&config.catalog_db)?;
+        for (file, imported) in [
+            (
+                record_file,
+                catalog.import_downloaded_ids(list, record_file)?,
+            ),
+            (
+                config.media_download_record.as_str(),
+                catalog.import_media_records(&config.media_download_record)?,
+            ),
+        ] {
+            if imported > 0 {
+                info!(
+                    "已将 {} 中的 {} 条记录导入 {}",
+                    file, imported, config.catalog_db
+                );
+            }
+        }
+        let downloaded_ids = catalog.downloaded_ids(list)?;
+        let photo_variants = PhotoVariants::load(&config.photo_variants_file)?;
+        let references = Self::load_downloaded_ids(&config.referenced_tweets_record)?;
+        // 不可用记录每行为 "<推文ID> <原因>"，只取 ID
+        let unavailable_ids = Self::load_downloaded_ids(&config.unavailable_tweets_record)?
+            .iter()
+            .filter_map(|line| line.split_whitespace().next().map(str::to_string))
+            .collect();
+
+        let overall = logging::progress().add(ProgressBar::new(0));
+        overall.set_style(
+            ProgressStyle::default_bar()
+                .template("总进度 [{bar:40.green/white}] {pos}/{len} tweets")
+                .unwrap()
+                .progress_chars("=>-"),
+        );
 
         Ok(Downloader {
+            video_policy: VideoPolicy::parse(&config.video_quality)?,
             client,
+            download_slots: Semaphore::new(config.concurrency.max(1)),
             config,
-            downloaded_ids,
+            catalog: Arc::new(catalog),
+            list: list.to_string(),
+            downloaded_ids: Mutex::new(downloaded_ids),
+            host_slots: Mutex::new(HashMap::new()),
+            references: Mutex::new(references),
+            unavailable_ids: Mutex::new(unavailable_ids),
+            photo_variants,
+            overall,
         })
     }
 
@@ -46,8 +231,124 @@ impl Downloader {
         Ok(content.lines().map(|s| s.trim().to_string()).collect())
     }
 
-    pub fn save_downloaded_id(&mut self, tweet_id: &str) -> Result<()> {
-        if let Some(parent) = Path::new(&self.config.download_record).parent() {
+    /// 在阻塞线程池中访问数据库，避免同步的 SQLite 读写阻塞其它下载任务
+    async fn with_catalog<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Catalog) -> Result<T> + Send + 'static,
+    {
+        let catalog = Arc::clone(&self.catalog);
+        tokio::task::spawn_blocking(move || f(&catalog)).await?
+    }
+
+    /// 记录推文已下载完成，`sort_index` 为推文在时间线中的排序键
+    pub async fn save_downloaded_id(&self, tweet_id: &str, sort_index: Option<u128>) -> Result<()> {
+        if self.is_downloaded(tweet_id) {
+            return Ok(());
+        }
+
+        // 同一推文重复写入时只更新排序键，不需要在写入期间持有锁
+        let (list, id) = (self.list.clone(), tweet_id.to_string());
+        self.with_catalog(move |catalog| catalog.save_download(&list, &id, sort_index))
+            .await?;
+        self.downloaded_ids
+            .lock()
+            .unwrap()
+            .insert(tweet_id.to_string());
+        Ok(())
+    }
+
+    /// 在数据库中记录媒体的下载结果。没有下载时计算的哈希时重新计算，
+    /// 跳过的已存在文件若路径和大小未变，沿用已计算的哈希
+    async fn record_media(&self, job: &MediaJob, outcome: &MediaOutcome) -> Result<()> {
+        let (status, path, known_hash, reuse_hash) = match outcome {
+            MediaOutcome::Skipped(None) => return Ok(()),
+            MediaOutcome::Skipped(Some(path)) => (MediaStatus::Done, Some(path), None, true),
+            MediaOutcome::Downloaded { path, sha256, .. } => {
+                (MediaStatus::Done, Some(path), sha256.clone(), false)
+            }
+            MediaOutcome::Gone => (MediaStatus::Gone, None, None, false),
+            MediaOutcome::Failed => (MediaStatus::Failed, None, None, false),
+        };
+        let photo_size = match outcome {
+            MediaOutcome::Downloaded {
+                photo_size: Some((size, tried)),
+                ..
+            } => Some((size.to_string(), tried.to_string())),
+            _ => None,
+        };
+
+        let mut file = MediaFile {
+            tweet_id: job.tweet_id.clone(),
+            media_id: job.media.id.clone(),
+            url: job.media.url.clone(),
+            path: None,
+            size: None,
+            sha256: None,
+            status,
+            photo_size: photo_size.as_ref().map(|(size, _)| size.clone()),
+            photo_size_tried: photo_size.map(|(_, tried)| tried),
+        };
+        if let Some(path) = path {
+            let size = tokio::fs::metadata(path).await?.len();
+            let path = path.to_string_lossy().to_string();
+            let mut known_hash = known_hash;
+            if reuse_hash {
+                let (tweet_id, media_id, recorded_path) =
+                    (job.tweet_id.clone(), job.media.id.clone(), path.clone());
+                known_hash = self
+                    .with_catalog(move |catalog| catalog.media(&tweet_id, &media_id))
+                    .await?
+                    .filter(|m| m.path == Some(recorded_path) && m.size == Some(size))
+                    .and_then(|m| m.sha256);
+            }
+            file.sha256 = Some(match known_hash {
+                Some(hash) => hash,
+                None => {
+                    let hash_path = PathBuf::from(&path);
+                    tokio::task::spawn_blocking(move || file_sha256(&hash_path)).await??
+                }
+            });
+            file.path = Some(path);
+            file.size = Some(size);
+        }
+        self.with_catalog(move |catalog| catalog.save_media(&file))
+            .await
+    }
+
+    /// 在 UNAVAILABLE_TWEETS_RECORD 中追加一行 `<推文ID> <原因>`，同一推文只记录一次
+    pub fn save_unavailable(&self, tweet_id: &str, reason: &str) -> Result<()> {
+        let mut unavailable_ids = self.unavailable_ids.lock().unwrap();
+        if unavailable_ids.contains(tweet_id) {
+            return Ok(());
+        }
+
+        let record_file = &self.config.unavailable_tweets_record;
+        if let Some(parent) = Path::new(record_file).parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        use std::io::Write;
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(record_file)?
+            .write_all(format!("{} {}\n", tweet_id, reason.replace('\n', " ")).as_bytes())?;
+
+        unavailable_ids.insert(tweet_id.to_string());
+        Ok(())
+    }
+
+    /// 在 REFERENCED_TWEETS_RECORD 中追加一行 `<来源推文ID> <quoted|retweeted> <原推文ID>`
+    fn save_reference(&self, tweet_id: &str, relation: &str, referenced_id: &str) -> Result<()> {
+        let line = format!("{} {} {}", tweet_id, relation, referenced_id);
+        let mut references = self.references.lock().unwrap();
+        if references.contains(&line) {
+            return Ok(());
+        }
+
+        let record_file = &self.config.referenced_tweets_record;
+        if let Some(parent) = Path::new(record_file).parent() {
             fs::create_dir_all(parent)?;
         }
 
@@ -55,254 +356,713 @@ impl Downloader {
         fs::OpenOptions::new()
             .create(true)
             .append(true)
-            .open(&self.config.download_record)?
-            .write_all(format!("{}\n", tweet_id).as_bytes())?;
+            .open(record_file)?
+            .write_all(format!("{}\n", line).as_bytes())?;
 
-        self.downloaded_ids.insert(tweet_id.to_string());
+        references.insert(line);
         Ok(())
     }
 
     pub fn is_downloaded(&self, tweet_id: &str) -> bool {
-        self.downloaded_ids.contains(tweet_id)
+        self.downloaded_ids.lock().unwrap().contains(tweet_id)
     }
 
     pub fn downloaded_count(&self) -> usize {
-        self.downloaded_ids.len()
+        self.downloaded_ids.lock().unwrap().len()
     }
 
-    pub async fn call_media_downloader(
+    /// 同时处理的推文数
+    pub fn concurrency(&self) -> usize {
+        self.config.concurrency.max(1)
+    }
+
+    /// 总进度条，按页追加待处理的推文数
+    pub fn overall_progress(&self) -> &ProgressBar {
+        &self.overall
+    }
+
+    /// 需要下载媒体的推文：本推文，或开启 FOLLOW_QUOTES_AND_RETWEETS 时的转推原推文及引用推文
+    fn media_sources<'a>(
         &self,
-        tweet: &Value,
+        tweet: &'a Tweet,
         tweet_id: &str,
-    ) -> Result<Option<bool>> {
-        // 从 tweet 对象中抽取推文主体
-        let tweet_obj = self.extract_tweet_object(tweet)?;
-        if tweet_obj.is_none() {
-            return Ok(None);
+    ) -> Vec<(&'a Tweet, String, Option<&'static str>)> {
+        if self.config.follow_quotes_and_retweets {
+            referenced_tweets(tweet, tweet_id)
+        } else {
+            vec![(tweet, tweet_id.to_string(), None)]
         }
-        let tweet_obj = tweet_obj.unwrap();
+    }
 
-        // 提取推文发布时间
-        let tweet_timestamp = self.extract_tweet_timestamp(tweet_obj)?;
+    /// 推文中是否有可下载的媒体，没有媒体的推文不会被记录为已下载
+    pub fn has_media(&self, tweet: &Tweet) -> bool {
+        self.media_sources(tweet, &tweet.rest_id)
+            .iter()
+            .any(|(source, _, _)| !self.extract_media_urls(source).is_empty())
+    }
+
+    /// 下载推文中的全部媒体。返回 None 表示没有媒体；Some(true) 表示全部媒体都已下载或已不存在，
+    /// 此时才应记录推文 ID，部分失败时返回 Some(false)，下次运行时只重试失败的媒体。
+    /// `skip_recorded` 为 false 时（如 --upgrade）不按媒体下载记录跳过已完成的媒体
+    pub async fn call_media_downloader(
+        &self,
+        tweet: &Tweet,
+        tweet_id: &str,
+        skip_recorded: bool,
+    ) -> Result<Option<bool>> {
+        let sources = self.media_sources(tweet, tweet_id);
 
-        // 提取作者用户名
-        let username = self.extract_username(tweet_obj)?;
+        // 每个媒体文件以所属推文的作者和 ID 命名
+        let mut jobs = Vec::new();
+        for (source, source_id, _) in &sources {
+            // 构建文件名前缀
+            let prefix = self
+                .config
+                .file_format
+                .replace("{USERNAME}", source.screen_name().unwrap_or_default())
+                .replace("{ID}", source_id)
+                .replace(" ", "_");
 
-        // 提取媒体列表
-        let media_urls = self.extract_media_urls(tweet_obj)?;
-        if media_urls.is_empty() {
+            let media_list = self.extract_media_urls(source);
+            if !media_list.is_empty() {
+                let tweet = (*source).clone();
+                self.with_catalog(move |catalog| catalog.save_tweet(&tweet))
+                    .await?;
+            }
+            for media in media_list {
+                jobs.push(MediaJob {
+                    tweet_id: source_id.clone(),
+                    prefix: prefix.clone(),
+                    timestamp: source.timestamp(),
+                    metadata: (self.config.metadata_sidecar || self.config.embed_metadata).then(
+                        || Sidecar::new(source, source_id, &media.url, media.alt_text.as_deref()),
+                    ),
+                    media,
+                });
+            }
+        }
+        if jobs.is_empty() {
             return Ok(None);
         }
 
-        // 构建文件名前缀
-        let prefix = self
-            .config
-            .file_format
-            .replace("{USERNAME}", &username.unwrap_or_default())
-            .replace("{ID}", tweet_id)
-            .replace(" ", "_");
-
         let output_dir = Path::new(&self.config.download_dir);
         fs::create_dir_all(output_dir)?;
 
+        let total_media_count = jobs.len();
+
+        // 同一推文的多个媒体并发下载，实际并发数由全局和每主机的信号量限制
+        let outcomes = join_all(jobs.iter().enumerate().map(|(i, job)| {
+            self.download_one(job, output_dir, skip_recorded, i + 1, total_media_count)
+        }))
+        .await;
+
         let mut download_success_count = 0;
-        let total_media_count = media_urls.len();
         let mut skipped_count = 0;
-
-        for (i, media_url) in media_urls.iter().enumerate() {
-            let parsed_url = Url::parse(media_url)?;
-            let original_name = parsed_url
-                .path_segments()
-                .and_then(|mut segments| segments.next_back())
-                .unwrap_or("unknown");
-
-            let filename = format!("{}_{}", prefix, original_name);
-            let out_path = output_dir.join(&filename);
-
-            // 检查文件是否已存在
-            if out_path.exists() {
-                let metadata = fs::metadata(&out_path)?;
-                if metadata.len() > 0 {
-                    println!(
-                        "跳过已存在的文件 ({}/{}): {:?} (大小: {} 字节)",
-                        i + 1,
-                        total_media_count,
-                        out_path,
-                        metadata.len()
-                    );
+        let mut gone_count = 0;
+        for (outcome, job) in outcomes.into_iter().zip(&jobs) {
+            let outcome = outcome.unwrap_or_else(|e| {
+                error!("下载异常: {} 错误: {}", job.media.url, e);
+                MediaOutcome::Failed
+            });
+            match outcome {
+                MediaOutcome::Skipped(_) => {
                     skipped_count += 1;
                     download_success_count += 1;
-                    continue;
-                } else {
-                    println!(
-                        "发现损坏的空文件，将重新下载 ({}/{}): {:?}",
-                        i + 1,
-                        total_media_count,
-                        out_path
-                    );
-                    fs::remove_file(&out_path)?;
                 }
+                MediaOutcome::Downloaded { .. } => download_success_count += 1,
+                MediaOutcome::Gone => gone_count += 1,
+                MediaOutcome::Failed => {}
             }
+            self.record_media(job, &outcome).await?;
+        }
 
-            match self
-                .download_media(media_url, &out_path, i + 1, total_media_count)
-                .await
-            {
-                Ok(true) => {
-                    // 下载成功后设置文件时间为推文发布时间
-                    if let Some(ts) = tweet_timestamp {
-                        let ft = FileTime::from_unix_time(ts, 0);
-                        if let Err(e) = set_file_times(&out_path, ft, ft) {
-                            println!("设置文件时间失败 {:?}: {}", out_path, e);
-                        }
-                    }
-                    download_success_count += 1;
-                }
-                Ok(false) => {
-                    // 下载失败，继续下一个
-                }
-                Err(e) => {
-                    println!(
-                        "下载异常 ({}/{}): {} 错误: {}",
-                        i + 1,
-                        total_media_count,
-                        media_url,
-                        e
-                    );
+        if download_success_count > 0 {
+            // 引用和转推的媒体以原推文命名，记录它们来自哪条推文
+            for (_, source_id, relation) in &sources {
+                if let Some(relation) = relation {
+                    self.save_reference(tweet_id, relation, source_id)?;
                 }
             }
         }
 
-        if download_success_count > 0 {
-            if skipped_count > 0 {
-                println!(
-                    "Tweet {} 处理完成: 跳过 {} 个已存在文件，成功下载 {} 个新文件",
-                    tweet_id,
-                    skipped_count,
-                    download_success_count - skipped_count
+        let failed_count = total_media_count - download_success_count - gone_count;
+        if failed_count > 0 {
+            if download_success_count > 0 {
+                warn!(
+                    "Tweet {} 有 {}/{} 个媒体文件下载失败，下次运行时重试",
+                    tweet_id, failed_count, total_media_count
+                );
+            } else {
+                warn!("Tweet {} 所有媒体文件下载失败", tweet_id);
+            }
+            return Ok(Some(false));
+        }
+
+        if gone_count > 0 {
+            warn!(
+                "Tweet {} 有 {} 个媒体文件已不存在 (404)，不再重试",
+                tweet_id, gone_count
+            );
+        }
+        if skipped_count > 0 {
+            info!(
+                "Tweet {} 处理完成: 跳过 {} 个已存在文件，成功下载 {} 个新文件",
+                tweet_id,
+                skipped_count,
+                download_success_count - skipped_count
+            );
+        } else if download_success_count > 0 {
+            info!(
+                "Tweet {} 成功下载了 {}/{} 个媒体文件",
+                tweet_id, download_success_count, total_media_count
+            );
+        }
+        Ok(Some(true))
+    }
+
+    async fn download_one(
+        &self,
+        job: &MediaJob,
+        output_dir: &Path,
+        skip_recorded: bool,
+        current: usize,
+        total: usize,
+    ) -> Result<MediaOutcome> {
+        let MediaJob {
+            media,
+            tweet_id,
+            prefix,
+            timestamp: tweet_timestamp,
+            metadata: tweet_metadata,
+        } = job;
+
+        let (recorded_tweet_id, media_id) = (tweet_id.clone(), media.id.clone());
+        let recorded = self
+            .with_catalog(move |catalog| catalog.media(&recorded_tweet_id, &media_id))
+            .await?;
+
+        let parsed_url = Url::parse(&media.url)?;
+        let original_name = parsed_url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .unwrap_or("unknown");
+
+        // 图片按 PHOTO_QUALITY 从大到小尝试各个尺寸，其它媒体只有一个地址
+        let photo_sizes = match media.kind {
+            MediaKind::Photo => photo_sizes_from(&self.config.photo_quality),
+            _ => &[],
+        };
+
+        let filename = match media.kind {
+            MediaKind::Photo => {
+                let (stem, ext) = original_name
+                    .rsplit_once('.')
+                    .unwrap_or((original_name, "jpg"));
+                let format = if self.config.photo_format.is_empty() {
+                    ext
+                } else {
+                    &self.config.photo_format
+                };
+                format!("{}_{}.{}", prefix, stem, format)
+            }
+            MediaKind::Hls { .. } => {
+                let stem = original_name
+                    .rsplit_once('.')
+                    .map_or(original_name, |(s, _)| s);
+                format!("{}_{}.mp4", prefix, stem)
+            }
+            _ => format!("{}_{}", prefix, original_name),
+        };
+        let out_path = output_dir.join(&filename);
+
+        // 之前下载的图片尺寸低于 PHOTO_QUALITY（或没有记录）
+        let below_quality = photo_sizes
+            .first()
+            .is_some_and(|size| !self.photo_is_at_least(recorded.as_ref(), &filename, size));
+
+        // 已确认不存在的媒体不再请求；已完成的媒体即使文件已被整理走也不再下载。
+        // --upgrade 时只重新下载尺寸较小的图片，按尺寸记录判断，不依赖文件是否还在
+        match recorded.as_ref().map(|m| m.status) {
+            Some(MediaStatus::Gone) => {
+                debug!("媒体已不存在，跳过 ({}/{}): {}", current, total, media.url);
+                return Ok(MediaOutcome::Gone);
+            }
+            Some(MediaStatus::Done) if skip_recorded || !below_quality => {
+                debug!("媒体已下载，跳过 ({}/{}): {}", current, total, media.url);
+                return Ok(MediaOutcome::Skipped(None));
+            }
+            _ => {}
+        }
+
+        // 动图需要转换时，以转换后的文件作为最终文件
+        let converted_path = self.gif_convert_path(media, &out_path);
+        let mut final_path = converted_path.clone().unwrap_or_else(|| out_path.clone());
+
+        // HLS 的 TS 分片拼接后保存为 .ts
+        let ts_path = out_path.with_extension("ts");
+        if matches!(media.kind, MediaKind::Hls { .. }) && !final_path.exists() && ts_path.exists() {
+            final_path = ts_path;
+        }
+
+        // 检查文件是否已存在
+        let mut upgrading = false;
+        if final_path.exists() {
+            let metadata = fs::metadata(&final_path)?;
+            if metadata.len() == 0 {
+                warn!(
+                    "发现损坏的空文件，将重新下载 ({}/{}): {:?}",
+                    current, total, final_path
+                );
+                fs::remove_file(&final_path)?;
+            } else if below_quality {
+                // 之前下载的图片尺寸较小（或没有记录），重新下载以升级
+                upgrading = true;
+                info!(
+                    "已存在的图片尺寸低于 {}，重新下载 ({}/{}): {:?}",
+                    photo_sizes[0], current, total, final_path
                 );
             } else {
-                println!(
-                    "Tweet {} 成功下载了 {}/{} 个媒体文件",
-                    tweet_id, download_success_count, total_media_count
+                debug!(
+                    "跳过已存在的文件 ({}/{}): {:?} (大小: {} 字节)",
+                    current,
+                    total,
+                    final_path,
+                    metadata.len()
                 );
+                // 之前未开启 METADATA_SIDECAR 时下载的文件补写元数据
+                if let Some(tweet_metadata) = tweet_metadata {
+                    if self.config.metadata_sidecar && !Sidecar::path_for(&final_path).exists() {
+                        write_sidecar(tweet_metadata, &final_path).await;
+                    }
+                }
+                return Ok(MediaOutcome::Skipped(Some(final_path)));
+            }
+        }
+
+        // 上次转换失败时保留了 mp4，直接重新转换
+        let mp4_ready = converted_path.is_some()
+            && fs::metadata(&out_path).is_ok_and(|metadata| metadata.len() > 0);
+
+        let mut saved_path = out_path.clone();
+        let mut saved_hash = None;
+        let mut photo_size = None;
+        if !mp4_ready {
+            // 先取得主机配额再取得全局配额，避免占着全局配额等待某个繁忙主机
+            let host_slots = self.host_slots(parsed_url.host_str().unwrap_or(""));
+            let _host_permit = host_slots.acquire().await?;
+            let _permit = self.download_slots.acquire().await?;
+
+            let downloaded = if self.config.mock_mode {
+                let copied = self.copy_mock_media(original_name, &out_path, current, total)?;
+                photo_size = photo_sizes.first().map(|size| (*size, *size));
+                copied
+                    .then(|| (out_path.clone(), None))
+                    .ok_or(DownloadStatus::Failed)
+            } else if let MediaKind::Hls { duration_ms } = media.kind {
+                self.download_hls(&parsed_url, duration_ms, &out_path, current, total)
+                    .await?
+                    .map(|path| (path, None))
+                    .ok_or(DownloadStatus::Failed)
+            } else {
+                let status = if photo_sizes.is_empty() {
+                    self.download_media(&media.url, &out_path, current, total)
+                        .await?
+                } else {
+                    let (status, size) = self
+                        .download_photo(&parsed_url, photo_sizes, &out_path, current, total)
+                        .await?;
+                    // 同时记录尝试过的最大尺寸，之后升级时不再重复请求不存在的尺寸
+                    photo_size = size.map(|size| (size, photo_sizes[0]));
+                    status
+                };
+                match status {
+                    DownloadStatus::Done(hash) => Ok((out_path.clone(), Some(hash))),
+                    status => Err(status),
+                }
+            };
+
+            match downloaded {
+                Ok((path, hash)) => (saved_path, saved_hash) = (path, hash),
+                Err(_) if upgrading => {
+                    warn!("图片升级失败，保留原文件: {:?}", out_path);
+                    return Ok(MediaOutcome::Skipped(Some(out_path)));
+                }
+                Err(DownloadStatus::NotFound) => return Ok(MediaOutcome::Gone),
+                Err(_) => return Ok(MediaOutcome::Failed),
+            }
+        }
+
+        if let Some(converted_path) = converted_path {
+            if self.convert_gif(&out_path, &converted_path).await {
+                fs::remove_file(&out_path)
+                    .with_context(|| format!("无法删除已转换的文件 {:?}", out_path))?;
+                saved_path = converted_path;
+                saved_hash = None;
+            }
+        }
+
+        // 写入元数据会修改文件时间，因此在设置文件时间之前进行
+        if let Some(tweet_metadata) = tweet_metadata {
+            if self.config.embed_metadata {
+                // 图片整体读入内存、MP4 可能需要移动整个文件，放到阻塞线程池中执行
+                let (path, metadata) = (saved_path.clone(), tweet_metadata.clone());
+                let embedded =
+                    tokio::task::spawn_blocking(move || embed::embed_metadata(&path, &metadata))
+                        .await
+                        .map_err(anyhow::Error::from)
+                        .and_then(|result| result);
+                match embedded {
+                    Ok(true) => saved_hash = None,
+                    Ok(false) => debug!("该文件格式不支持写入元数据: {:?}", saved_path),
+                    Err(e) => {
+                        saved_hash = None;
+                        warn!("写入元数据失败 {:?}: {:#}", saved_path, e);
+                    }
+                }
+            }
+            if self.config.metadata_sidecar {
+                write_sidecar(tweet_metadata, &saved_path).await;
+            }
+        }
+
+        // 下载成功后设置文件时间为推文发布时间
+        if let Some(ts) = *tweet_timestamp {
+            let ft = FileTime::from_unix_time(ts, 0);
+            if let Err(e) = set_file_times(&saved_path, ft, ft) {
+                warn!("设置文件时间失败 {:?}: {}", saved_path, e);
             }
-            Ok(Some(true))
-        } else {
-            println!("Tweet {} 所有媒体文件下载失败", tweet_id);
-            Ok(Some(false))
         }
+        Ok(MediaOutcome::Downloaded {
+            path: saved_path,
+            sha256: saved_hash,
+            photo_size,
+        })
     }
 
-    fn extract_tweet_object<'a>(&self, tweet: &'a Value) -> Result<Option<&'a Value>> {
-        // 路径1: content.itemContent.tweet_results.result.tweet
-        if let Some(tweet_obj) = tweet
-            .get("content")
-            .and_then(|c| c.get("itemContent"))
-            .and_then(|ic| ic.get("tweet_results"))
-            .and_then(|tr| tr.get("result"))
-            .and_then(|r| r.get("tweet"))
-        {
-            return Ok(Some(tweet_obj));
+    /// 已下载的图片是否不小于 `size`：优先按数据库中的尺寸记录判断，没有记录时按旧版的记录文件判断
+    fn photo_is_at_least(&self, recorded: Option<&MediaFile>, filename: &str, size: &str) -> bool {
+        match recorded.and_then(|m| m.photo_size.as_deref()) {
+            Some(photo_size) => photo_size_at_least(
+                photo_size,
+                recorded.and_then(|m| m.photo_size_tried.as_deref()),
+                size,
+            ),
+            None => self.photo_variants.is_at_least(filename, size),
         }
+    }
+
+    /// 从大到小依次尝试图片尺寸，某个尺寸返回 404 时换用下一个，成功时同时返回实际下载的尺寸。
+    /// 所有尺寸都返回 404 时为 NotFound
+    async fn download_photo(
+        &self,
+        url: &Url,
+        sizes: &[&'static str],
+        out_path: &Path,
+        current: usize,
+        total: usize,
+    ) -> Result<(DownloadStatus, Option<&'static str>)> {
+        let format = out_path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_string())
+            .unwrap_or_else(|| "jpg".to_string());
 
-        // 路径2: 直接结构
-        if let Some(tweet_obj) = tweet.get("tweet") {
-            return Ok(Some(tweet_obj));
+        for (i, size) in sizes.iter().enumerate() {
+            let variant_url = photo_variant_url(url, &format, size);
+            match self
+                .download_media(&variant_url, out_path, current, total)
+                .await?
+            {
+                DownloadStatus::Done(hash) => return Ok((DownloadStatus::Done(hash), Some(size))),
+                DownloadStatus::NotFound => match sizes.get(i + 1) {
+                    Some(next) => warn!(
+                        "图片尺寸 {} 不存在，改为下载 {} ({}/{}): {:?}",
+                        size, next, current, total, out_path
+                    ),
+                    None => warn!("下载失败 (404 Not Found) ({}/{}): {}", current, total, url),
+                },
+                DownloadStatus::Failed => return Ok((DownloadStatus::Failed, None)),
+            }
         }
 
-        // 路径3: 使用原始tweet
-        Ok(Some(tweet))
+        Ok((DownloadStatus::NotFound, None))
     }
 
-    fn extract_username(&self, tweet_obj: &Value) -> Result<Option<String>> {
-        let username = tweet_obj
-            .get("core")
-            .and_then(|c| c.get("user_results"))
-            .and_then(|ur| ur.get("result"))
-            .and_then(|r| r.get("legacy"))
-            .and_then(|l| l.get("screen_name"))
-            .and_then(|s| s.as_str());
+    /// 配置了 GIF_CONVERT_COMMAND 时，动图转换后的文件路径
+    fn gif_convert_path(&self, media: &Media, out_path: &Path) -> Option<PathBuf> {
+        if media.kind != MediaKind::AnimatedGif || self.config.gif_convert_command.trim().is_empty()
+        {
+            return None;
+        }
+        Some(out_path.with_extension(&self.config.gif_convert_format))
+    }
 
-        Ok(username.map(|s| s.to_string()))
+    /// 调用外部编码命令将动图 mp4 转换为 gif/webp，失败时保留 mp4
+    async fn convert_gif(&self, input: &Path, output: &Path) -> bool {
+        let args = command_args(
+            &self.config.gif_convert_command,
+            &[("{input}", input), ("{output}", output)],
+        );
+        match run_external_command(&args, output).await {
+            Ok(()) => {
+                info!("动图转换成功: {:?}", output);
+                true
+            }
+            Err(error) => {
+                warn!("动图转换失败，保留 mp4 文件 {:?} ({})", input, error);
+                false
+            }
+        }
     }
 
-    fn extract_media_urls(&self, tweet_obj: &Value) -> Result<Vec<String>> {
-        let mut media_urls = Vec::new();
+    /// 下载 HLS 视频：主播放列表按 VIDEO_QUALITY 选择清晰度，再依次下载分片拼接成一个文件。
+    /// 音轨单独提供时先拼接为 `_audio` 文件，再与视频合并；无法合并时视为下载失败
+    async fn download_hls(
+        &self,
+        url: &Url,
+        duration_ms: Option<u64>,
+        out_path: &Path,
+        current: usize,
+        total: usize,
+    ) -> Result<Option<PathBuf>> {
+        let result: Result<PathBuf> = async {
+            let text = String::from_utf8_lossy(&self.fetch_bytes(url).await?).to_string();
+            let (playlist_url, text, audio_url) = if hls::is_master(&text) {
+                let variants = hls::parse_master(&text, url)?;
+                let variant = hls::select_variant(&variants, duration_ms, &self.video_policy)
+                    .ok_or_else(|| anyhow::anyhow!("HLS 主播放列表中没有可用的清晰度"))?;
+                let text = self.fetch_bytes(&variant.uri).await?;
+                (
+                    variant.uri.clone(),
+                    String::from_utf8_lossy(&text).to_string(),
+                    variant.audio.clone(),
+                )
+            } else {
+                (url.clone(), text, None)
+            };
 
-        let legacy = tweet_obj.get("legacy").unwrap_or(&Value::Null);
-
-        // 从 extended_entities 或 entities 中提取媒体列表
-        let media_list = legacy
-            .get("extended_entities")
-            .and_then(|ee| ee.get("media"))
-            .and_then(|m| m.as_array())
-            .or_else(|| {
-                legacy
-                    .get("entities")
-                    .and_then(|e| e.get("media"))
-                    .and_then(|m| m.as_array())
-            });
+            let playlist = hls::parse_media(&text, &playlist_url)?;
+            let video_path = out_path.with_extension(playlist.extension());
+            self.fetch_segments(&playlist, &video_path).await?;
 
-        if let Some(media_array) = media_list {
-            for media in media_array {
-                let media_type = media.get("type").and_then(|t| t.as_str()).unwrap_or("");
-
-                match media_type {
-                    "video" => {
-                        if let Some(video_info) = media.get("video_info") {
-                            if let Some(variants) =
-                                video_info.get("variants").and_then(|v| v.as_array())
-                            {
-                                let best_variant = variants
-                                    .iter()
-                                    .filter(|v| v.get("bitrate").is_some())
-                                    .max_by_key(|v| {
-                                        v.get("bitrate").and_then(|b| b.as_u64()).unwrap_or(0)
-                                    });
-
-                                if let Some(variant) = best_variant {
-                                    if let Some(url) = variant.get("url").and_then(|u| u.as_str()) {
-                                        media_urls.push(url.to_string());
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    "photo" => {
-                        if let Some(url) = media.get("media_url_https").and_then(|u| u.as_str()) {
-                            media_urls.push(url.to_string());
-                        }
-                    }
-                    _ => {}
-                }
+            let Some(audio_url) = audio_url else {
+                return Ok(video_path);
+            };
+            let text = self.fetch_bytes(&audio_url).await?;
+            let audio_playlist = hls::parse_media(&String::from_utf8_lossy(&text), &audio_url)?;
+            let audio_ext = match audio_playlist.extension() {
+                "mp4" => "m4a",
+                ext => ext,
+            };
+            let stem = out_path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let audio_path = out_path.with_file_name(format!("{}_audio.{}", stem, audio_ext));
+            self.fetch_segments(&audio_playlist, &audio_path).await?;
+
+            self.merge_hls(&video_path, &audio_path, out_path).await
+        }
+        .await;
+
+        match result {
+            Ok(path) => {
+                info!("下载成功 (HLS) ({}/{}): {:?}", current, total, path);
+                Ok(Some(path))
+            }
+            Err(e) => {
+                warn!("下载失败 (HLS) ({}/{}): {} ({})", current, total, url, e);
+                Ok(None)
             }
         }
+    }
+
+    /// 合并视频和音轨：配置了 HLS_MERGE_COMMAND 时调用外部命令，否则 fMP4 分片由程序本身合并，
+    /// TS 分片没有配置合并命令时返回错误。分开的视频和音轨不会被记录，无论是否合并成功都会删除
+    async fn merge_hls(
+        &self,
+        video_path: &Path,
+        audio_path: &Path,
+        out_path: &Path,
+    ) -> Result<PathBuf> {
+        let merged_path = out_path.with_extension("merging.mp4");
+        let merged = if self.config.hls_merge_command.trim().is_empty() {
+            let is_fmp4 = |path: &Path| path.extension().is_some_and(|ext| ext != "ts");
+            if is_fmp4(video_path) && is_fmp4(audio_path) {
+                let (video, audio, merged) = (
+                    video_path.to_path_buf(),
+                    audio_path.to_path_buf(),
+                    merged_path.clone(),
+                );
+                tokio::task::spawn_blocking(move || mux::merge_fmp4(&video, &audio, &merged))
+                    .await
+                    .map_err(anyhow::Error::from)
+                    .and_then(|result| result)
+            } else {
+                Err(anyhow::anyhow!(
+                    "TS 分片的音轨需要配置 HLS_MERGE_COMMAND 才能合并"
+                ))
+            }
+        } else {
+            let args = command_args(
+                &self.config.hls_merge_command,
+                &[
+                    ("{video}", video_path),
+                    ("{audio}", audio_path),
+                    ("{output}", &merged_path),
+                ],
+            );
+            run_external_command(&args, &merged_path)
+                .await
+                .map_err(|e| anyhow::anyhow!("合并 HLS 音轨失败: {}", e))
+        };
 
-        Ok(media_urls)
+        // fMP4 视频与合并结果同名，先删除分开的文件再重命名；合并失败时下次运行重新下载
+        fs::remove_file(video_path).ok();
+        fs::remove_file(audio_path).ok();
+        merged?;
+        if let Err(e) = fs::rename(&merged_path, out_path) {
+            fs::remove_file(&merged_path).ok();
+            return Err(anyhow::Error::new(e).context(format!("无法重命名 {:?}", merged_path)));
+        }
+        Ok(out_path.to_path_buf())
     }
 
-    fn extract_tweet_timestamp(&self, tweet_obj: &Value) -> Result<Option<i64>> {
-        let legacy = tweet_obj.get("legacy").unwrap_or(&Value::Null);
+    /// 依次下载初始化分片和各个分片，写入 .part 文件，全部完成后再重命名
+    async fn fetch_segments(&self, playlist: &hls::MediaPlaylist, path: &Path) -> Result<()> {
+        let part_path = Self::part_path(path);
+        let pb = logging::progress().insert_before(
+            &self.overall,
+            ProgressBar::new(playlist.segments.len() as u64),
+        );
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} 分片 {msg}")
+                .unwrap()
+                .progress_chars("#>-"),
+        );
+        pb.set_message(
+            path.file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        );
 
-        // 尝试 created_at_ms （毫秒时间戳）
-        if let Some(ts_ms_str) = legacy.get("created_at_ms").and_then(|v| v.as_str()) {
-            if let Ok(ts_ms) = ts_ms_str.parse::<i64>() {
-                return Ok(Some(ts_ms / 1000));
+        let result: Result<()> = async {
+            let mut file = File::create(&part_path).await?;
+            if let Some(init) = &playlist.init {
+                file.write_all(&self.fetch_bytes(init).await?).await?;
+            }
+            for segment in &playlist.segments {
+                file.write_all(&self.fetch_bytes(segment).await?).await?;
+                pb.inc(1);
             }
+            file.flush().await?;
+            fs::rename(&part_path, path).with_context(|| format!("无法重命名 {:?}", part_path))
+        }
+        .await;
+
+        pb.finish_and_clear();
+        if result.is_err() {
+            fs::remove_file(&part_path).ok();
         }
-        if let Some(ts_ms) = legacy.get("created_at_ms").and_then(|v| v.as_i64()) {
-            return Ok(Some(ts_ms / 1000));
+        result
+    }
+
+    /// 获取播放列表或分片，遇到 429、5xx 或网络错误时按指数退避重试
+    async fn fetch_bytes(&self, url: &Url) -> Result<Vec<u8>> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let response = self
+                .client
+                .get(url.clone())
+                .header("User-Agent", &self.config.user_agent)
+                .send()
+                .await;
+
+            let error = match response {
+                Ok(response) if response.status().is_success() => match response.bytes().await {
+                    Ok(bytes) => return Ok(bytes.to_vec()),
+                    Err(e) => e.to_string(),
+                },
+                Ok(response) if !is_retryable_status(response.status()) => {
+                    return Err(anyhow::anyhow!("{} 返回 {}", url, response.status()));
+                }
+                Ok(response) => response.status().to_string(),
+                Err(e) => e.to_string(),
+            };
+
+            if attempt > self.config.max_retries {
+                return Err(anyhow::anyhow!("请求 {} 失败: {}", url, error));
+            }
+            tokio::time::sleep(backoff_delay(attempt)).await;
         }
+    }
+
+    fn host_slots(&self, host: &str) -> Arc<Semaphore> {
+        self.host_slots
+            .lock()
+            .unwrap()
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.config.per_host_concurrency.max(1))))
+            .clone()
+    }
 
-        // 尝试 created_at 字符串，如 "Thu Apr 06 15:24:15 +0000 2017"
-        if let Some(created_at_str) = legacy.get("created_at").and_then(|v| v.as_str()) {
-            if let Ok(dt) = DateTime::parse_from_str(created_at_str, "%a %b %d %H:%M:%S %z %Y") {
-                return Ok(Some(dt.timestamp()));
+    fn extract_media_urls(&self, tweet: &Tweet) -> Vec<Media> {
+        let mut media_urls = Vec::new();
+
+        for media in tweet.media() {
+            match media.kind.as_str() {
+                // 动图和视频一样以 mp4 形式提供，动图通常只有一个码率为 0 的变体
+                "video" | "animated_gif" => {
+                    let Some(video_info) = &media.video_info else {
+                        continue;
+                    };
+                    let variants = &video_info.variants;
+                    let duration_ms = video_info.duration_millis;
+                    let best_variant =
+                        select_video_variant(variants, duration_ms, &self.video_policy);
+
+                    let kind = if media.kind == "animated_gif" {
+                        MediaKind::AnimatedGif
+                    } else {
+                        MediaKind::Video
+                    };
+
+                    // 没有 mp4 变体时改用 HLS 播放列表
+                    let (variant, kind) = match best_variant {
+                        Some(variant) => (Some(variant), kind),
+                        None => (
+                            variants.iter().find(|v| {
+                                v.content_type.as_deref() == Some("application/x-mpegURL")
+                            }),
+                            MediaKind::Hls { duration_ms },
+                        ),
+                    };
+
+                    if let Some(variant) = variant {
+                        media_urls.push(Media {
+                            id: media_id(media, &variant.url),
+                            url: variant.url.clone(),
+                            kind,
+                            alt_text: media.ext_alt_text.clone(),
+                        });
+                    }
+                }
+                "photo" => {
+                    if let Some(url) = &media.media_url_https {
+                        media_urls.push(Media {
+                            id: media_id(media, url),
+                            url: url.clone(),
+                            kind: MediaKind::Photo,
+                            alt_text: media.ext_alt_text.clone(),
+                        });
+                    }
+                }
+                _ => {}
             }
         }
 
-        Ok(None)
+        media_urls
     }
 
     async fn download_media(
@@ -311,61 +1071,441 @@ impl Downloader {
         out_path: &Path,
         current: usize,
         total: usize,
-    ) -> Result<bool> {
+    ) -> Result<DownloadStatus> {
+        // 先写入 .part 文件，完整后再重命名到最终路径
+        let part_path = Self::part_path(out_path);
+
+        // 为当前下载创建进度条，显示在总进度条上方
+        let pb = logging::progress().insert_before(&self.overall, ProgressBar::new(0));
+        pb.set_style(ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta}) {msg}")
+            .unwrap()
+            .progress_chars("#>-"));
+        pb.set_message(
+            out_path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        );
+
+        // 没有进展的中断才累计重试次数，有进展时重新计数
+        let mut attempts = 0;
+        loop {
+            match self.fetch_to_part(url, &part_path, &pb).await? {
+                PartStatus::Complete(size, hash) => {
+                    pb.finish_and_clear();
+                    fs::rename(&part_path, out_path)
+                        .with_context(|| format!("无法重命名 {:?}", part_path))?;
+                    info!(
+                        "下载成功 ({}/{}): {:?} (大小: {} 字节)",
+                        current, total, out_path, size
+                    );
+                    return Ok(DownloadStatus::Done(hash));
+                }
+                PartStatus::Failed(StatusCode::NOT_FOUND) => {
+                    pb.finish_and_clear();
+                    // 媒体已不存在，已下载的部分没有用处（图片换用其它尺寸时内容也不同）
+                    fs::remove_file(&part_path).ok();
+                    return Ok(DownloadStatus::NotFound);
+                }
+                PartStatus::Failed(status)
+                    if is_retryable_status(status) && attempts < self.config.max_retries =>
+                {
+                    attempts += 1;
+                    warn!(
+                        "下载失败 ({}) ({}/{}): {}，第 {}/{} 次重试",
+                        status, current, total, url, attempts, self.config.max_retries
+                    );
+                }
+                PartStatus::Failed(status) => {
+                    pb.finish_and_clear();
+                    warn!("下载失败 ({}) ({}/{}): {}", status, current, total, url);
+                    return Ok(DownloadStatus::Failed);
+                }
+                PartStatus::Interrupted { progressed, reason } => {
+                    attempts = if progressed { 0 } else { attempts + 1 };
+                    let downloaded = fs::metadata(&part_path).map(|m| m.len()).unwrap_or(0);
+                    if attempts > self.config.max_retries {
+                        pb.finish_and_clear();
+                        warn!(
+                            "下载不完整 ({}/{}): {:?} ({})，已保留 {} 字节，下次运行时继续",
+                            current, total, out_path, reason, downloaded
+                        );
+                        return Ok(DownloadStatus::Failed);
+                    }
+                    warn!(
+                        "下载中断 ({}/{}): {:?} ({})，从 {} 字节处继续",
+                        current, total, out_path, reason, downloaded
+                    );
+                }
+            }
+            tokio::time::sleep(backoff_delay(attempts)).await;
+        }
+    }
+
+    fn part_path(out_path: &Path) -> PathBuf {
+        let mut part = out_path.as_os_str().to_owned();
+        part.push(".part");
+        PathBuf::from(part)
+    }
+
+    /// 下载到 .part 文件。已有部分内容时发送 Range 请求，服务器返回 206 则追加，返回 200 则从头写入。
+    /// 写入的同时计算文件的 SHA-256，追加时先读入已下载的部分
+    async fn fetch_to_part(
+        &self,
+        url: &str,
+        part_path: &Path,
+        pb: &ProgressBar,
+    ) -> Result<PartStatus> {
+        let existing = fs::metadata(part_path).map(|m| m.len()).unwrap_or(0);
+
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert("User-Agent", self.config.user_agent.parse()?);
+        if existing > 0 {
+            headers.insert(RANGE, format!("bytes={}-", existing).parse()?);
+        }
 
-        let response = self.client.get(url).headers(headers).send().await?;
+        let response = match self.client.get(url).headers(headers).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                return Ok(PartStatus::Interrupted {
+                    progressed: false,
+                    reason: e.to_string(),
+                })
+            }
+        };
 
-        if !response.status().is_success() {
-            println!(
-                "下载失败 ({}) ({}/{}): {}",
-                response.status(),
-                current,
-                total,
-                url
-            );
-            return Ok(false);
+        let status = response.status();
+        if status == StatusCode::RANGE_NOT_SATISFIABLE && existing > 0 {
+            // Content-Range: bytes */<总大小>，与已下载大小一致说明文件其实已经完整
+            let total_size = response
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.rsplit_once('/'))
+                .and_then(|(_, size)| size.parse::<u64>().ok());
+            if total_size == Some(existing) {
+                let hasher = part_hasher(part_path, existing).await?;
+                return Ok(PartStatus::Complete(
+                    existing,
+                    format!("{:x}", hasher.finalize()),
+                ));
+            }
+            fs::remove_file(part_path)?;
+            return Ok(PartStatus::Interrupted {
+                progressed: false,
+                reason: "已下载部分无效，重新下载".to_string(),
+            });
         }
 
-        let content_length = response.content_length();
-        let mut downloaded_size = 0u64;
+        if !status.is_success() {
+            return Ok(PartStatus::Failed(status));
+        }
 
-        // 创建进度条
-        let pb = ProgressBar::new(content_length.unwrap_or(0));
-        pb.set_style(ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-            .unwrap()
-            .progress_chars("#>-"));
+        let offset = if status == StatusCode::PARTIAL_CONTENT {
+            // Content-Range: bytes <起始>-<结束>/<总大小>，起始位置与已下载大小不一致时不能追加
+            let start = response
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("bytes "))
+                .and_then(|v| v.split_once('-'))
+                .and_then(|(start, _)| start.trim().parse::<u64>().ok());
+            if start != Some(existing) {
+                fs::remove_file(part_path)?;
+                return Ok(PartStatus::Interrupted {
+                    progressed: false,
+                    reason: format!(
+                        "服务器返回的内容范围与已下载部分不一致 ({:?})，重新下载",
+                        start
+                    ),
+                });
+            }
+            existing
+        } else {
+            0
+        };
+        let mut hasher = part_hasher(part_path, offset).await?;
+        let mut file = if offset > 0 {
+            OpenOptions::new().append(true).open(part_path).await?
+        } else {
+            File::create(part_path).await?
+        };
+
+        let expected_size = response.content_length().map(|len| len + offset);
+        pb.set_length(expected_size.unwrap_or(0));
+        pb.set_position(offset);
 
-        let mut file = File::create(out_path).await?;
+        let mut downloaded_size = offset;
         let mut stream = response.bytes_stream();
+        let mut interrupted = None;
 
         while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
-            file.write_all(&chunk).await?;
-            downloaded_size += chunk.len() as u64;
-            pb.set_position(downloaded_size);
+            match chunk {
+                Ok(chunk) => {
+                    file.write_all(&chunk).await?;
+                    hasher.update(&chunk);
+                    downloaded_size += chunk.len() as u64;
+                    pb.set_position(downloaded_size);
+                }
+                Err(e) => {
+                    interrupted = Some(e.to_string());
+                    break;
+                }
+            }
         }
-
-        pb.finish_with_message("下载完成");
+        file.flush().await?;
 
         // 验证下载完整性
-        if let Some(expected_size) = content_length {
-            if downloaded_size != expected_size {
-                println!(
-                    "下载不完整 ({}/{}): {:?} (期望: {}, 实际: {})",
-                    current, total, out_path, expected_size, downloaded_size
-                );
-                fs::remove_file(out_path)?;
-                return Ok(false);
+        let progressed = downloaded_size > offset;
+        match (interrupted, expected_size) {
+            (Some(reason), _) => Ok(PartStatus::Interrupted { progressed, reason }),
+            (None, Some(expected)) if downloaded_size < expected => Ok(PartStatus::Interrupted {
+                progressed,
+                reason: format!("期望: {}, 实际: {}", expected, downloaded_size),
+            }),
+            (None, Some(expected)) if downloaded_size > expected => {
+                fs::remove_file(part_path)?;
+                Ok(PartStatus::Interrupted {
+                    progressed: false,
+                    reason: format!(
+                        "文件大小超出预期 (期望: {}, 实际: {})",
+                        expected, downloaded_size
+                    ),
+                })
             }
+            _ => Ok(PartStatus::Complete(
+                downloaded_size,
+                format!("{:x}", hasher.finalize()),
+            )),
+        }
+    }
+
+    /// Mock 模式下从本地夹具目录复制媒体文件，按媒体 URL 的最后一段路径匹配文件名
+    fn copy_mock_media(
+        &self,
+        original_name: &str,
+        out_path: &Path,
+        current: usize,
+        total: usize,
+    ) -> Result<bool> {
+        let fixture_path = Path::new(&self.config.mock_media_dir).join(original_name);
+
+        if !fixture_path.is_file() {
+            warn!(
+                "下载失败 (Mock 文件不存在) ({}/{}): {:?}",
+                current, total, fixture_path
+            );
+            return Ok(false);
         }
 
-        println!(
-            "下载成功 ({}/{}): {:?} (大小: {} 字节)",
-            current, total, out_path, downloaded_size
+        let size = fs::copy(&fixture_path, out_path)
+            .with_context(|| format!("无法复制 Mock 文件: {:?}", fixture_path))?;
+
+        info!(
+            "下载成功 (Mock) ({}/{}): {:?} (大小: {} 字节)",
+            current, total, out_path, size
         );
         Ok(true)
     }
 }
+
+/// 需要下载媒体的推文（推文对象、ID、与来源推文的关系）：转推只保留原推文，
+/// 因为转推本身的媒体就是原推文的媒体；再加上被引用的推文
+fn referenced_tweets<'a>(
+    tweet: &'a Tweet,
+    tweet_id: &str,
+) -> Vec<(&'a Tweet, String, Option<&'static str>)> {
+    let mut sources = Vec::new();
+
+    let main = match referenced_result(tweet.legacy.retweeted_status_result.as_deref()) {
+        Some(original) => {
+            sources.push((original, original.rest_id.clone(), Some("retweeted")));
+            original
+        }
+        None => {
+            sources.push((tweet, tweet_id.to_string(), None));
+            tweet
+        }
+    };
+
+    if let Some(quoted) = referenced_result(main.quoted_status_result.as_deref()) {
+        sources.push((quoted, quoted.rest_id.clone(), Some("quoted")));
+    }
+
+    sources
+}
+
+/// 写入元数据文件，失败时只警告，不影响媒体本身的下载结果
+async fn write_sidecar(sidecar: &Sidecar, media_path: &Path) {
+    let (sidecar, media_path) = (sidecar.clone(), media_path.to_path_buf());
+    let written = tokio::task::spawn_blocking(move || sidecar.write(&media_path))
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|result| result);
+    if let Err(e) = written {
+        warn!("{:#}", e);
+    }
+}
+
+/// 读入 .part 文件的前 `len` 字节，返回用于继续计算 SHA-256 的状态
+async fn part_hasher(part_path: &Path, len: u64) -> Result<Sha256> {
+    let mut hasher = Sha256::new();
+    if len == 0 {
+        return Ok(hasher);
+    }
+
+    let file = File::open(part_path)
+        .await
+        .with_context(|| format!("无法读取文件: {:?}", part_path))?;
+    let mut reader = file.take(len);
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher)
+}
+
+/// 文件内容的 SHA-256（十六进制），读取整个文件，需在阻塞线程池中调用
+fn file_sha256(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path).with_context(|| format!("无法读取文件: {:?}", path))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// 媒体在推文中的标识：优先使用 id_str，缺失时退回到 URL 中的文件名
+fn media_id(media: &MediaEntity, url: &str) -> String {
+    media.id_str.clone().unwrap_or_else(|| {
+        let path = url.split(['?', '#']).next().unwrap_or(url);
+        let name = path.rsplit('/').next().unwrap_or(path);
+        name.split('.').next().unwrap_or(name).to_string()
+    })
+}
+
+/// 从 `*_status_result` 中取出推文，已删除或不可见的推文返回 None
+fn referenced_result(status_result: Option<&TweetResults>) -> Option<&Tweet> {
+    match status_result?.result.as_ref()? {
+        TweetResult::Tweet(tweet) => Some(tweet),
+        TweetResult::Unavailable(_) => None,
+    }
+}
+
+/// 按策略从视频变体中选择一个 mp4，只考虑带 bitrate 的变体（m3u8 没有 bitrate）。
+/// 没有变体满足分辨率或大小上限时退回码率最低的变体
+fn select_video_variant<'a>(
+    variants: &'a [VideoVariant],
+    duration_ms: Option<u64>,
+    policy: &VideoPolicy,
+) -> Option<&'a VideoVariant> {
+    let bitrate = |v: &VideoVariant| v.bitrate.unwrap_or(0);
+    let mp4s = || variants.iter().filter(|v| v.bitrate.is_some());
+    let lowest = || mp4s().min_by_key(|v| bitrate(v));
+
+    match *policy {
+        VideoPolicy::Highest => mp4s().max_by_key(|v| bitrate(v)),
+        VideoPolicy::Lowest => lowest(),
+        VideoPolicy::MaxResolution(max_height) => mp4s()
+            .filter_map(|v| {
+                let (width, height) = variant_resolution(v)?;
+                (width.min(height) <= max_height).then_some((width * height, bitrate(v), v))
+            })
+            .max_by_key(|(pixels, bitrate, _)| (*pixels, *bitrate))
+            .map(|(_, _, v)| v)
+            .or_else(lowest),
+        VideoPolicy::MaxSizeMb(max_mb) => {
+            // 按码率和时长估算文件大小，没有时长时无法估算，按码率最高处理
+            let Some(duration_ms) = duration_ms else {
+                return mp4s().max_by_key(|v| bitrate(v));
+            };
+            mp4s()
+                .filter(|v| bitrate(v) / 8 * duration_ms / 1000 <= max_mb * 1024 * 1024)
+                .max_by_key(|v| bitrate(v))
+                .or_else(lowest)
+        }
+    }
+}
+
+/// 从变体 URL 中解析分辨率，如 .../vid/avc1/1280x720/abc.mp4
+fn variant_resolution(variant: &VideoVariant) -> Option<(u64, u64)> {
+    let url = Url::parse(&variant.url).ok()?;
+    url.path_segments()?.find_map(|segment| {
+        let (width, height) = segment.split_once('x')?;
+        Some((width.parse().ok()?, height.parse().ok()?))
+    })
+}
+
+/// 从配置的尺寸开始往下的尺寸列表，未知尺寸按 orig 处理
+fn photo_sizes_from(quality: &str) -> &'static [&'static str] {
+    let start = PHOTO_SIZES
+        .iter()
+        .position(|size| *size == quality)
+        .unwrap_or(0);
+    &PHOTO_SIZES[start..]
+}
+
+/// 构造指定格式和尺寸的图片地址，如 https://pbs.twimg.com/media/abc?format=jpg&name=orig
+fn photo_variant_url(url: &Url, format: &str, size: &str) -> String {
+    let mut variant = url.clone();
+    let path = url.path();
+    let stem = path.rsplit_once('.').map_or(path, |(stem, _)| stem);
+    variant.set_path(stem);
+    variant
+        .query_pairs_mut()
+        .clear()
+        .append_pair("format", format)
+        .append_pair("name", size);
+    variant.to_string()
+}
+
+/// 按空白拆分外部命令，并将占位符（如 {input}、{output}）替换为文件路径
+///
+/// 先拆分再替换，路径中含空格也不会被拆开
+fn command_args(command: &str, placeholders: &[(&str, &Path)]) -> Vec<String> {
+    command
+        .split_whitespace()
+        .map(|arg| {
+            placeholders
+                .iter()
+                .fold(arg.to_string(), |arg, (placeholder, path)| {
+                    arg.replace(placeholder, &path.to_string_lossy())
+                })
+        })
+        .collect()
+}
+
+/// 执行外部命令，要求命令成功退出并生成输出文件；失败时清理可能残留的不完整输出
+async fn run_external_command(args: &[String], output: &Path) -> Result<(), String> {
+    let Some((program, args)) = args.split_first() else {
+        return Err("命令为空".to_string());
+    };
+
+    let result = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .output()
+        .await;
+
+    let error = match result {
+        Ok(result) if result.status.success() && output.is_file() => return Ok(()),
+        Ok(result) if result.status.success() => "命令未生成输出文件".to_string(),
+        Ok(result) => format!(
+            "{}: {}",
+            result.status,
+            String::from_utf8_lossy(&result.stderr).trim()
+        ),
+        Err(e) => format!("无法执行 {}: {}", program, e),
+    };
+
+    fs::remove_file(output).ok();
+    Err(error)
+}
+
+#[cfg(test)]
+#[path = "downloader_test.rs"]
+mod tests;
@@ -0,0 +1,458 @@
+use super::*;
+use serde_json::{json, Value};
+
+fn parse_tweet(value: Value) -> Tweet {
+    serde_json::from_value(value).unwrap()
+}
+
+fn downloader() -> Downloader {
+    let record = std::env::temp_dir().join(format!(
+        "x_likes_downloader_{}_missing_record.txt",
+        std::process::id()
+    ));
+    let config = Config {
+        catalog_db: ":memory:".to_string(),
+        ..Default::default()
+    };
+    Downloader::new(config, "likes", &record.to_string_lossy()).unwrap()
+}
+
+#[test]
+fn test_extract_media_urls_includes_animated_gif() {
+    let tweet = parse_tweet(json!({
+        "rest_id": "1",
+        "legacy": { "extended_entities": { "media": [
+            { "type": "photo", "id_str": "11", "media_url_https": "https://pbs.twimg.com/media/a.jpg", "ext_alt_text": "一只猫" },
+            { "type": "animated_gif", "video_info": { "variants": [
+                { "bitrate": 0, "content_type": "video/mp4", "url": "https://video.twimg.com/tweet_video/b.mp4" }
+            ] } },
+            { "type": "video", "video_info": { "variants": [
+                { "content_type": "application/x-mpegURL", "url": "https://video.twimg.com/c.m3u8" },
+                { "bitrate": 256000, "content_type": "video/mp4", "url": "https://video.twimg.com/c_low.mp4" },
+                { "bitrate": 2176000, "content_type": "video/mp4", "url": "https://video.twimg.com/c_high.mp4" }
+            ] } }
+        ] } }
+    }));
+
+    let media = downloader().extract_media_urls(&tweet);
+    assert_eq!(
+        media,
+        vec![
+            Media {
+                id: "11".to_string(),
+                url: "https://pbs.twimg.com/media/a.jpg".to_string(),
+                kind: MediaKind::Photo,
+                alt_text: Some("一只猫".to_string()),
+            },
+            Media {
+                id: "b".to_string(),
+                url: "https://video.twimg.com/tweet_video/b.mp4".to_string(),
+                kind: MediaKind::AnimatedGif,
+                alt_text: None,
+            },
+            Media {
+                id: "c_high".to_string(),
+                url: "https://video.twimg.com/c_high.mp4".to_string(),
+                kind: MediaKind::Video,
+                alt_text: None,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_photo_variant_url_and_fallback_sizes() {
+    let url = Url::parse("https://pbs.twimg.com/media/ABC123.jpg").unwrap();
+    assert_eq!(
+        photo_variant_url(&url, "png", "orig"),
+        "https://pbs.twimg.com/media/ABC123?format=png&name=orig"
+    );
+
+    assert_eq!(photo_sizes_from("orig"), &PHOTO_SIZES[..]);
+    assert_eq!(photo_sizes_from("large"), &["large", "medium", "small"]);
+}
+
+#[test]
+fn test_command_args_keeps_paths_with_spaces() {
+    let args = command_args(
+        "ffmpeg -y -i {input} {output}",
+        &[
+            ("{input}", Path::new("data/my downloads/a_b.mp4")),
+            ("{output}", Path::new("data/my downloads/a_b.gif")),
+        ],
+    );
+    assert_eq!(
+        args,
+        vec![
+            "ffmpeg",
+            "-y",
+            "-i",
+            "data/my downloads/a_b.mp4",
+            "data/my downloads/a_b.gif"
+        ]
+    );
+}
+
+#[test]
+fn test_photo_variants_upgrade_check() {
+    let variants: PhotoVariants = serde_json::from_value(json!({
+        "variants": { "a_1_x.jpg": "large", "b_1_x.jpg": "4096x4096" },
+        "tried": { "b_1_x.jpg": "orig" }
+    }))
+    .unwrap();
+    // 没有记录的文件视为较小的默认尺寸
+    assert!(!variants.is_at_least("c_1_x.jpg", "large"));
+
+    assert!(variants.is_at_least("a_1_x.jpg", "large"));
+    assert!(variants.is_at_least("a_1_x.jpg", "medium"));
+    assert!(!variants.is_at_least("a_1_x.jpg", "orig"));
+
+    // orig 返回 404 后改为下载 4096x4096，之后不再尝试升级到 orig
+    assert!(variants.is_at_least("b_1_x.jpg", "orig"));
+
+    // 只确认过 large 不存在时，仍可升级到 orig
+    assert!(photo_size_at_least("medium", Some("large"), "large"));
+    assert!(!photo_size_at_least("medium", Some("large"), "orig"));
+}
+
+fn video_variants() -> Vec<VideoVariant> {
+    serde_json::from_value(json!([
+        { "content_type": "application/x-mpegURL", "url": "https://video.twimg.com/ext_tw_video/1/pu/pl/a.m3u8" },
+        { "bitrate": 256000, "url": "https://video.twimg.com/ext_tw_video/1/pu/vid/avc1/480x270/a.mp4" },
+        { "bitrate": 2176000, "url": "https://video.twimg.com/ext_tw_video/1/pu/vid/avc1/1280x720/b.mp4" },
+        { "bitrate": 10368000, "url": "https://video.twimg.com/ext_tw_video/1/pu/vid/avc1/1920x1080/c.mp4" }
+    ]))
+    .unwrap()
+}
+
+/// 按策略选择变体，只返回文件名便于断言
+fn selected_file(policy: &str, duration_ms: Option<u64>) -> String {
+    let variants = video_variants();
+    let policy = VideoPolicy::parse(policy).unwrap();
+    let variant = select_video_variant(&variants, duration_ms, &policy).unwrap();
+    variant.url.rsplit('/').next().unwrap().to_string()
+}
+
+#[test]
+fn test_select_video_variant_policies() {
+    assert_eq!(selected_file("highest", None), "c.mp4");
+    assert_eq!(selected_file("lowest", None), "a.mp4");
+    assert_eq!(selected_file("720p", None), "b.mp4");
+    // 没有满足上限的变体时退回码率最低的
+    assert_eq!(selected_file("144p", None), "a.mp4");
+
+    // 60 秒：c 约 74MB，b 约 15.6MB
+    assert_eq!(selected_file("50MB", Some(60_000)), "b.mp4");
+    assert_eq!(selected_file("100mb", Some(60_000)), "c.mp4");
+    assert_eq!(selected_file("1mb", Some(60_000)), "a.mp4");
+
+    assert!(VideoPolicy::parse("best").is_err());
+}
+
+#[test]
+fn test_extract_media_urls_falls_back_to_hls() {
+    let tweet = parse_tweet(json!({
+        "rest_id": "1",
+        "legacy": { "extended_entities": { "media": [
+            { "type": "video", "video_info": { "duration_millis": 15000, "variants": [
+                { "content_type": "application/x-mpegURL", "url": "https://video.twimg.com/amplify_video/1/pl/master.m3u8?tag=16" }
+            ] } }
+        ] } }
+    }));
+
+    let media = downloader().extract_media_urls(&tweet);
+    assert_eq!(
+        media,
+        vec![Media {
+            id: "master".to_string(),
+            url: "https://video.twimg.com/amplify_video/1/pl/master.m3u8?tag=16".to_string(),
+            kind: MediaKind::Hls {
+                duration_ms: Some(15000)
+            },
+            alt_text: None,
+        }]
+    );
+}
+
+#[test]
+fn test_referenced_tweets_follow_retweet_and_quote() {
+    let tweet = parse_tweet(json!({
+        "rest_id": "100",
+        "legacy": { "retweeted_status_result": { "result": {
+            "rest_id": "200",
+            "quoted_status_result": { "result": {
+                "__typename": "TweetWithVisibilityResults",
+                "tweet": { "rest_id": "300", "legacy": {} }
+            } },
+            "legacy": {}
+        } } }
+    }));
+
+    let sources: Vec<(String, Option<&str>)> = referenced_tweets(&tweet, "100")
+        .into_iter()
+        .map(|(_, id, relation)| (id, relation))
+        .collect();
+    // 转推只保留原推文，原推文引用的推文也一并下载
+    assert_eq!(
+        sources,
+        vec![
+            ("200".to_string(), Some("retweeted")),
+            ("300".to_string(), Some("quoted"))
+        ]
+    );
+
+    let plain = parse_tweet(json!({ "rest_id": "1", "legacy": {} }));
+    let sources = referenced_tweets(&plain, "1");
+    assert_eq!(sources.len(), 1);
+    assert_eq!(sources[0].1, "1");
+    assert!(sources[0].2.is_none());
+}
+
+#[tokio::test]
+async fn test_record_media_saves_size_and_hash() {
+    let downloader = downloader();
+    let path = std::env::temp_dir().join(format!(
+        "x_likes_downloader_{}_record_media.jpg",
+        std::process::id()
+    ));
+    fs::write(&path, "abc").unwrap();
+    let job = |id: &str| MediaJob {
+        media: Media {
+            id: id.to_string(),
+            url: format!("https://pbs.twimg.com/media/{}.jpg", id),
+            kind: MediaKind::Photo,
+            alt_text: None,
+        },
+        tweet_id: "1".to_string(),
+        prefix: "alice_1".to_string(),
+        timestamp: None,
+        metadata: None,
+    };
+
+    downloader
+        .record_media(
+            &job("11"),
+            &MediaOutcome::Downloaded {
+                path: path.clone(),
+                sha256: None,
+                photo_size: Some(("4096x4096", "orig")),
+            },
+        )
+        .await
+        .unwrap();
+    downloader
+        .record_media(&job("12"), &MediaOutcome::Gone)
+        .await
+        .unwrap();
+    // 下载时已计算的哈希直接使用，不再读取文件
+    downloader
+        .record_media(
+            &job("13"),
+            &MediaOutcome::Downloaded {
+                path: path.clone(),
+                sha256: Some("streamed".to_string()),
+                photo_size: None,
+            },
+        )
+        .await
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let done = downloader.catalog.media("1", "11").unwrap().unwrap();
+    assert_eq!(done.status, MediaStatus::Done);
+    assert_eq!(done.size, Some(3));
+    assert_eq!(
+        done.sha256.as_deref(),
+        Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+    );
+    assert_eq!(done.photo_size.as_deref(), Some("4096x4096"));
+    assert_eq!(done.photo_size_tried.as_deref(), Some("orig"));
+    let gone = downloader.catalog.media("1", "12").unwrap().unwrap();
+    assert_eq!((gone.status, gone.path), (MediaStatus::Gone, None));
+    let streamed = downloader.catalog.media("1", "13").unwrap().unwrap();
+    assert_eq!(streamed.sha256.as_deref(), Some("streamed"));
+}
+
+/// 在本地端口上对每个请求返回同一个固定的 HTTP 响应，返回媒体地址
+async fn serve(response: &'static str) -> String {
+    use tokio::io::AsyncReadExt;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut request = [0; 4096];
+            let _ = socket.read(&mut request).await;
+            let _ = socket.write_all(response.as_bytes()).await;
+        }
+    });
+    format!("http://{}/media/a.mp4", addr)
+}
+
+fn local_downloader() -> Downloader {
+    let mut config = Config {
+        catalog_db: ":memory:".to_string(),
+        ..Default::default()
+    };
+    config.proxy.no_proxy = true;
+    Downloader::new(config, "likes", "").unwrap()
+}
+
+#[tokio::test]
+async fn test_fetch_to_part_rejects_mismatched_content_range() {
+    // 服务器忽略了请求的起始位置，从头返回内容
+    let url = serve(
+        "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 0-4/5\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+    )
+    .await;
+    let part_path = std::env::temp_dir().join(format!(
+        "x_likes_downloader_{}_range.mp4.part",
+        std::process::id()
+    ));
+    fs::write(&part_path, "hel").unwrap();
+
+    let status = local_downloader()
+        .fetch_to_part(&url, &part_path, &ProgressBar::hidden())
+        .await
+        .unwrap();
+    assert!(matches!(
+        status,
+        PartStatus::Interrupted {
+            progressed: false,
+            ..
+        }
+    ));
+    assert!(!part_path.exists());
+}
+
+#[tokio::test]
+async fn test_download_media_removes_part_on_not_found() {
+    let url =
+        serve("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").await;
+    let out_path = std::env::temp_dir().join(format!(
+        "x_likes_downloader_{}_gone.mp4",
+        std::process::id()
+    ));
+    let part_path = Downloader::part_path(&out_path);
+    fs::write(&part_path, "stale").unwrap();
+
+    let status = local_downloader()
+        .download_media(&url, &out_path, 1, 1)
+        .await
+        .unwrap();
+    assert!(matches!(status, DownloadStatus::NotFound));
+    assert!(!part_path.exists());
+}
+
+#[tokio::test]
+async fn test_fetch_to_part_hashes_resumed_download() {
+    let url = serve(
+        "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 3-4/5\r\nContent-Length: 2\r\nConnection: close\r\n\r\nlo",
+    )
+    .await;
+    let part_path = std::env::temp_dir().join(format!(
+        "x_likes_downloader_{}_resume.mp4.part",
+        std::process::id()
+    ));
+    fs::write(&part_path, "hel").unwrap();
+
+    let status = local_downloader()
+        .fetch_to_part(&url, &part_path, &ProgressBar::hidden())
+        .await
+        .unwrap();
+    assert_eq!(fs::read_to_string(&part_path).unwrap(), "hello");
+    fs::remove_file(&part_path).unwrap();
+    // 哈希包含续传前已下载的部分
+    let PartStatus::Complete(size, hash) = status else {
+        panic!("应为 Complete");
+    };
+    assert_eq!(size, 5);
+    assert_eq!(
+        hash,
+        "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+    );
+}
+
+/// 一条推文带有两个视频，分别由不同的本地服务器返回
+fn two_video_tweet(tweet_id: &str, ok_url: &str, other_url: &str) -> Tweet {
+    let video = |url: &str| {
+        json!({ "type": "video", "video_info": { "variants": [
+            { "bitrate": 1, "content_type": "video/mp4", "url": url }
+        ] } })
+    };
+    parse_tweet(json!({
+        "rest_id": tweet_id,
+        "core": { "user_results": { "result": { "core": { "screen_name": "artist" } } } },
+        "legacy": { "extended_entities": { "media": [video(ok_url), video(other_url)] } }
+    }))
+}
+
+#[tokio::test]
+async fn test_call_media_downloader_records_only_complete_tweets() {
+    let dir =
+        std::env::temp_dir().join(format!("x_likes_downloader_{}_partial", std::process::id()));
+    let mut downloader = local_downloader();
+    downloader.config.download_dir = dir.to_string_lossy().to_string();
+    downloader.config.file_format = "{USERNAME}_{ID}".to_string();
+
+    let ok = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello";
+    let ok_url = serve(ok).await;
+    let server_error = serve(
+        "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+    )
+    .await
+    .replace("a.mp4", "b.mp4");
+    let not_found =
+        serve("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await
+            .replace("a.mp4", "b.mp4");
+
+    // 与 run_download 相同，只有全部媒体都已处理的推文才记录
+    let download = |tweet: Tweet| {
+        let downloader = &downloader;
+        async move {
+            let tweet_id = tweet.rest_id.clone();
+            let result = downloader
+                .call_media_downloader(&tweet, &tweet_id, true)
+                .await
+                .unwrap();
+            if result == Some(true) {
+                downloader
+                    .save_downloaded_id(&tweet_id, None)
+                    .await
+                    .unwrap();
+            }
+            result
+        }
+    };
+
+    // 一个成功、一个服务器错误：推文不记录，成功的媒体单独记录，失败的下次重试
+    assert_eq!(
+        download(two_video_tweet("1", &ok_url, &server_error)).await,
+        Some(false)
+    );
+    let done = downloader.catalog.media("1", "a").unwrap().unwrap();
+    assert_eq!(done.status, MediaStatus::Done);
+    assert_eq!(done.size, Some(5));
+    let failed = downloader.catalog.media("1", "b").unwrap().unwrap();
+    assert_eq!(failed.status, MediaStatus::Failed);
+    assert_eq!(failed.path, None);
+
+    // 一个成功、一个已不存在：推文视为完成
+    assert_eq!(
+        download(two_video_tweet("2", &ok_url, &not_found)).await,
+        Some(true)
+    );
+    assert_eq!(
+        downloader.catalog.media("2", "a").unwrap().unwrap().status,
+        MediaStatus::Done
+    );
+    assert_eq!(
+        downloader.catalog.media("2", "b").unwrap().unwrap().status,
+        MediaStatus::Gone
+    );
+
+    let downloaded = downloader.catalog.downloaded_ids("likes").unwrap();
+    fs::remove_dir_all(&dir).ok();
+    assert_eq!(downloaded, HashSet::from(["2".to_string()]));
+}
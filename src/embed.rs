@@ -0,0 +1,627 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::sidecar::Sidecar;
+
+/// JPEG APP1 中 XMP 数据的命名空间前缀
+const JPEG_XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// 一个 APP1 段最多 65535 字节（含长度字段），XMP 数据包只能使用命名空间之后剩余的部分
+const JPEG_XMP_MAX_LEN: usize = u16::MAX as usize - 2 - JPEG_XMP_NAMESPACE.len();
+/// PNG iTXt 中 XMP 数据的关键字
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp\0";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// VP8X 标志位中表示含有 XMP 的位
+const WEBP_XMP_FLAG: u8 = 0x04;
+const WEBP_ALPHA_FLAG: u8 = 0x10;
+
+/// 将推文链接、作者、正文和发布时间写入媒体文件：图片（JPEG/PNG/WebP）写入 XMP，
+/// MP4 写入 udta/meta/ilst 元数据。返回 false 表示该格式不支持（如 GIF、TS、分片 MP4）
+pub fn embed_metadata(path: &Path, metadata: &Sidecar) -> Result<bool> {
+    let mut header = [0u8; 12];
+    let read = File::open(path)
+        .and_then(|mut file| file.read(&mut header))
+        .with_context(|| format!("无法读取文件: {:?}", path))?;
+    let header = &header[..read];
+
+    let embedded = if header.starts_with(&[0xFF, 0xD8]) {
+        embed_image(path, &jpeg_xmp_packet(metadata), embed_jpeg)?
+    } else if header.starts_with(PNG_SIGNATURE) {
+        embed_image(path, &xmp_packet(metadata), embed_png)?
+    } else if header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WEBP") {
+        embed_image(path, &xmp_packet(metadata), embed_webp)?
+    } else if header.get(4..8) == Some(b"ftyp") {
+        embed_mp4(path, &mp4_items(metadata))?
+    } else {
+        false
+    };
+    Ok(embedded)
+}
+
+/// 图片整体读入内存处理，写入临时文件后替换原文件
+fn embed_image(path: &Path, xmp: &str, embed: fn(&[u8], &str) -> Result<Vec<u8>>) -> Result<bool> {
+    let data = fs::read(path).with_context(|| format!("无法读取文件: {:?}", path))?;
+    let output = embed(&data, xmp).with_context(|| format!("无法写入元数据: {:?}", path))?;
+    replace_file(path, |file| file.write_all(&output))?;
+    Ok(true)
+}
+
+fn replace_file(path: &Path, write: impl FnOnce(&mut File) -> io::Result<()>) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let result = File::create(&tmp_path)
+        .and_then(|mut file| write(&mut file).and_then(|_| file.sync_all()))
+        .and_then(|_| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result.with_context(|| format!("无法写入文件: {:?}", path))
+}
+
+/// XMP 数据包，使用 Dublin Core 和 XMP 基本命名空间
+fn xmp_packet(metadata: &Sidecar) -> String {
+    let mut fields = String::new();
+    if let Some(author) = author(metadata) {
+        fields += &format!(
+            "   <dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>\n",
+            xml_escape(&author)
+        );
+    }
+    if let Some(text) = &metadata.text {
+        fields += &format!(
+            "   <dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n",
+            xml_escape(text)
+        );
+    }
+    fields += &format!(
+        "   <dc:source>{}</dc:source>\n",
+        xml_escape(&metadata.tweet_url)
+    );
+    if let Some(created_at) = &metadata.created_at {
+        fields += &format!(
+            "   <xmp:CreateDate>{0}</xmp:CreateDate>\n   <photoshop:DateCreated>{0}</photoshop:DateCreated>\n",
+            xml_escape(created_at)
+        );
+    }
+
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n \
+         <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n  \
+         <rdf:Description rdf:about=\"\"\n    \
+         xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n    \
+         xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n    \
+         xmlns:photoshop=\"http://ns.adobe.com/photoshop/1.0/\">\n\
+         {}  </rdf:Description>\n \
+         </rdf:RDF>\n\
+         </x:xmpmeta>\n\
+         <?xpacket end=\"w\"?>",
+        fields
+    )
+}
+
+/// JPEG 的 XMP 数据包：超出一个 APP1 段的长度时截短正文，末尾加省略号
+fn jpeg_xmp_packet(metadata: &Sidecar) -> String {
+    let packet = xmp_packet(metadata);
+    let (Some(text), Some(overflow)) = (
+        &metadata.text,
+        packet
+            .len()
+            .checked_sub(JPEG_XMP_MAX_LEN)
+            .filter(|n| *n > 0),
+    ) else {
+        return packet;
+    };
+
+    // 正文转义后的长度需要减少 overflow，再留出省略号的位置
+    let budget = xml_escape(text)
+        .len()
+        .saturating_sub(overflow + '…'.len_utf8());
+    let mut used = 0;
+    let mut truncated: String = text
+        .chars()
+        .take_while(|c| {
+            used += xml_escape(c.encode_utf8(&mut [0; 4])).len();
+            used <= budget
+        })
+        .collect();
+    truncated.push('…');
+    xmp_packet(&Sidecar {
+        text: Some(truncated),
+        ..metadata.clone()
+    })
+}
+
+/// 作者，形如 `显示名称 (@用户名)`
+fn author(metadata: &Sidecar) -> Option<String> {
+    match (&metadata.display_name, &metadata.screen_name) {
+        (Some(name), Some(screen_name)) => Some(format!("{} (@{})", name, screen_name)),
+        (None, Some(screen_name)) => Some(format!("@{}", screen_name)),
+        (Some(name), None) => Some(name.clone()),
+        (None, None) => None,
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub(crate) fn invalid(format: &str) -> anyhow::Error {
+    anyhow!("{} 文件结构无效", format)
+}
+
+/// 在 JPEG 开头的 APP0/APP1（JFIF、EXIF）段之后插入 XMP 段，替换已有的 XMP 段
+fn embed_jpeg(data: &[u8], xmp: &str) -> Result<Vec<u8>> {
+    let mut segments = Vec::new();
+    let mut pos = 2;
+    let image_data = loop {
+        if data.get(pos) != Some(&0xFF) {
+            return Err(invalid("JPEG"));
+        }
+        // 标记前可以有多个填充的 0xFF
+        while data.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        let marker = *data.get(pos + 1).ok_or_else(|| invalid("JPEG"))?;
+        // SOS 之后是压缩数据，原样保留
+        if marker == 0xDA || marker == 0xD9 {
+            break &data[pos..];
+        }
+        let end = if matches!(marker, 0x01 | 0xD0..=0xD7) {
+            pos + 2
+        } else {
+            let len = data
+                .get(pos + 2..pos + 4)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+                .filter(|len| *len >= 2)
+                .ok_or_else(|| invalid("JPEG"))?;
+            pos + 2 + len
+        };
+        let segment = data.get(pos..end).ok_or_else(|| invalid("JPEG"))?;
+        segments.push((marker, segment));
+        pos = end;
+    };
+
+    segments.retain(|(marker, segment)| {
+        !(*marker == 0xE1 && segment[4..].starts_with(JPEG_XMP_NAMESPACE))
+    });
+
+    let payload_len = JPEG_XMP_NAMESPACE.len() + xmp.len();
+    let segment_len = u16::try_from(payload_len + 2).map_err(|_| anyhow!("XMP 元数据过长"))?;
+    let mut xmp_segment = vec![0xFF, 0xE1];
+    xmp_segment.extend_from_slice(&segment_len.to_be_bytes());
+    xmp_segment.extend_from_slice(JPEG_XMP_NAMESPACE);
+    xmp_segment.extend_from_slice(xmp.as_bytes());
+
+    let insert_at = segments
+        .iter()
+        .position(|(marker, _)| !matches!(marker, 0xE0 | 0xE1))
+        .unwrap_or(segments.len());
+
+    let mut output = Vec::with_capacity(data.len() + xmp_segment.len());
+    output.extend_from_slice(&data[..2]);
+    for (i, (_, segment)) in segments.iter().enumerate() {
+        if i == insert_at {
+            output.extend_from_slice(&xmp_segment);
+        }
+        output.extend_from_slice(segment);
+    }
+    if insert_at == segments.len() {
+        output.extend_from_slice(&xmp_segment);
+    }
+    output.extend_from_slice(image_data);
+    Ok(output)
+}
+
+/// 在 PNG 的 IHDR 之后插入 XMP iTXt 块，替换已有的 XMP 块
+fn embed_png(data: &[u8], xmp: &str) -> Result<Vec<u8>> {
+    let mut chunk_data = PNG_XMP_KEYWORD.to_vec();
+    // 未压缩，语言标签和翻译后的关键字为空
+    chunk_data.extend_from_slice(&[0, 0, 0, 0]);
+    chunk_data.extend_from_slice(xmp.as_bytes());
+    let xmp_chunk = png_chunk(b"iTXt", &chunk_data);
+
+    let mut output = Vec::with_capacity(data.len() + xmp_chunk.len());
+    output.extend_from_slice(PNG_SIGNATURE);
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < data.len() {
+        let len = data
+            .get(pos..pos + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .ok_or_else(|| invalid("PNG"))?;
+        let chunk = data
+            .get(pos..pos + 12 + len)
+            .ok_or_else(|| invalid("PNG"))?;
+        let kind = &chunk[4..8];
+        pos += chunk.len();
+
+        if kind == b"iTXt" && chunk[8..].starts_with(PNG_XMP_KEYWORD) {
+            continue;
+        }
+        output.extend_from_slice(chunk);
+        if kind == b"IHDR" {
+            output.extend_from_slice(&xmp_chunk);
+        }
+    }
+    Ok(output)
+}
+
+fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let crc = crc32(&chunk[4..]);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+    chunk
+}
+
+/// PNG 块使用的 CRC-32（IEEE 802.3）
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// 在 WebP 末尾添加 XMP 块并设置 VP8X 标志，简单格式（只有 VP8/VP8L）先转换为扩展格式
+fn embed_webp(data: &[u8], xmp: &str) -> Result<Vec<u8>> {
+    let mut chunks: Vec<([u8; 4], Vec<u8>)> = Vec::new();
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let kind: [u8; 4] = data[pos..pos + 4].try_into().unwrap();
+        let len = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let payload = data
+            .get(pos + 8..pos + 8 + len)
+            .ok_or_else(|| invalid("WebP"))?;
+        // 块长度为奇数时有一个填充字节
+        pos += 8 + len + len % 2;
+        if &kind != b"XMP " {
+            chunks.push((kind, payload.to_vec()));
+        }
+    }
+
+    match chunks.first_mut() {
+        Some((kind, payload)) if kind == b"VP8X" && !payload.is_empty() => {
+            payload[0] |= WEBP_XMP_FLAG;
+        }
+        Some((kind, payload)) => {
+            let (width, height, alpha) = webp_canvas(kind, payload)?;
+            let mut vp8x = vec![
+                WEBP_XMP_FLAG | if alpha { WEBP_ALPHA_FLAG } else { 0 },
+                0,
+                0,
+                0,
+            ];
+            // VP8X 中保存的是宽、高减 1，宽或高为 0 的图像数据无效
+            let (Some(width), Some(height)) = (width.checked_sub(1), height.checked_sub(1)) else {
+                return Err(invalid("WebP"));
+            };
+            vp8x.extend_from_slice(&width.to_le_bytes()[..3]);
+            vp8x.extend_from_slice(&height.to_le_bytes()[..3]);
+            chunks.insert(0, (*b"VP8X", vp8x));
+        }
+        None => return Err(invalid("WebP")),
+    }
+    chunks.push((*b"XMP ", xmp.as_bytes().to_vec()));
+
+    let mut body = b"WEBP".to_vec();
+    for (kind, payload) in &chunks {
+        body.extend_from_slice(kind);
+        body.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        body.extend_from_slice(payload);
+        if payload.len() % 2 == 1 {
+            body.push(0);
+        }
+    }
+    let mut output = b"RIFF".to_vec();
+    output.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    output.extend_from_slice(&body);
+    Ok(output)
+}
+
+/// 从简单格式 WebP 的图像数据中读取宽、高以及是否有透明通道
+fn webp_canvas(kind: &[u8; 4], payload: &[u8]) -> Result<(u32, u32, bool)> {
+    match kind {
+        b"VP8 " if payload.len() >= 10 && payload[3..6] == [0x9D, 0x01, 0x2A] => {
+            let width = u16::from_le_bytes([payload[6], payload[7]]) & 0x3FFF;
+            let height = u16::from_le_bytes([payload[8], payload[9]]) & 0x3FFF;
+            Ok((width as u32, height as u32, false))
+        }
+        b"VP8L" if payload.len() >= 5 && payload[0] == 0x2F => {
+            let bits = u32::from_le_bytes(payload[1..5].try_into().unwrap());
+            let width = (bits & 0x3FFF) + 1;
+            let height = ((bits >> 14) & 0x3FFF) + 1;
+            Ok((width, height, (bits >> 28) & 1 == 1))
+        }
+        _ => Err(invalid("WebP")),
+    }
+}
+
+/// MP4 元数据项：作者、发布时间、正文，推文链接写入注释
+fn mp4_items(metadata: &Sidecar) -> Vec<([u8; 4], String)> {
+    let mut items = Vec::new();
+    if let Some(author) = author(metadata) {
+        items.push((*b"\xA9ART", author));
+    }
+    if let Some(created_at) = &metadata.created_at {
+        items.push((*b"\xA9day", created_at.clone()));
+    }
+    if let Some(text) = &metadata.text {
+        items.push((*b"desc", text.clone()));
+    }
+    items.push((*b"\xA9cmt", metadata.tweet_url.clone()));
+    items
+}
+
+/// 顶层 box 的位置
+pub(crate) struct TopBox {
+    pub(crate) kind: [u8; 4],
+    pub(crate) offset: u64,
+    pub(crate) size: u64,
+}
+
+/// 只把 moov 读入内存修改，其他 box 原样复制。moov 变大后，位于其后的媒体数据偏移随之调整
+fn embed_mp4(path: &Path, items: &[([u8; 4], String)]) -> Result<bool> {
+    let mut file = File::open(path).with_context(|| format!("无法读取文件: {:?}", path))?;
+    let boxes = top_level_boxes(&mut file)?;
+    // 分片 MP4（如 HLS 拼接的视频）的数据偏移不在 moov 中，不处理
+    if boxes.iter().any(|b| &b.kind == b"moof") {
+        return Ok(false);
+    }
+    let moov = boxes
+        .iter()
+        .find(|b| &b.kind == b"moov")
+        .ok_or_else(|| anyhow!("MP4 文件缺少 moov"))?;
+
+    let mut moov_data = vec![0u8; moov.size as usize];
+    file.seek(SeekFrom::Start(moov.offset))?;
+    file.read_exact(&mut moov_data)?;
+    let mut new_moov = rebuild_moov(&moov_data, items)?;
+
+    let delta = new_moov.len() as i64 - moov.size as i64;
+    shift_chunk_offsets(&mut new_moov, moov.offset + moov.size, delta)?;
+
+    // 原文件在替换前关闭
+    let moov_offset = moov.offset;
+    replace_file(path, move |out| {
+        for b in &boxes {
+            if b.offset == moov_offset {
+                out.write_all(&new_moov)?;
+            } else {
+                file.seek(SeekFrom::Start(b.offset))?;
+                io::copy(&mut (&mut file).take(b.size), out)?;
+            }
+        }
+        Ok(())
+    })?;
+    Ok(true)
+}
+
+pub(crate) fn top_level_boxes(file: &mut File) -> Result<Vec<TopBox>> {
+    let len = file.metadata()?.len();
+    let mut boxes = Vec::new();
+    let mut offset = 0;
+    while offset + 8 <= len {
+        let mut header = [0u8; 16];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header[..8])?;
+        let kind: [u8; 4] = header[4..8].try_into().unwrap();
+        let size = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+            0 => len - offset,
+            1 => {
+                file.read_exact(&mut header[8..])?;
+                u64::from_be_bytes(header[8..].try_into().unwrap())
+            }
+            size => size as u64,
+        };
+        if size < 8 || offset + size > len {
+            return Err(invalid("MP4"));
+        }
+        boxes.push(TopBox { kind, offset, size });
+        offset += size;
+    }
+    Ok(boxes)
+}
+
+/// 内存中 box 的类型、在上级数据中的位置、头部长度和完整数据
+pub(crate) struct Mp4Box<'a> {
+    pub(crate) kind: [u8; 4],
+    pub(crate) offset: usize,
+    pub(crate) header_len: usize,
+    pub(crate) raw: &'a [u8],
+}
+
+impl Mp4Box<'_> {
+    pub(crate) fn payload(&self) -> &[u8] {
+        &self.raw[self.header_len..]
+    }
+}
+
+pub(crate) fn child_boxes(data: &[u8]) -> Result<Vec<Mp4Box<'_>>> {
+    let mut boxes = Vec::new();
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let kind: [u8; 4] = data[pos + 4..pos + 8].try_into().unwrap();
+        let (size, header_len) = match u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) {
+            0 => (data.len() - pos, 8),
+            1 => {
+                let size = data
+                    .get(pos + 8..pos + 16)
+                    .map(|b| u64::from_be_bytes(b.try_into().unwrap()) as usize)
+                    .ok_or_else(|| invalid("MP4"))?;
+                (size, 16)
+            }
+            size => (size as usize, 8),
+        };
+        let raw = data
+            .get(pos..pos + size)
+            .filter(|_| size >= header_len)
+            .ok_or_else(|| invalid("MP4"))?;
+        boxes.push(Mp4Box {
+            kind,
+            offset: pos,
+            header_len,
+            raw,
+        });
+        pos += size;
+    }
+    Ok(boxes)
+}
+
+pub(crate) fn make_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(payload.len() + 16);
+    match u32::try_from(payload.len() + 8) {
+        Ok(size) => data.extend_from_slice(&size.to_be_bytes()),
+        Err(_) => {
+            data.extend_from_slice(&1u32.to_be_bytes());
+            data.extend_from_slice(kind);
+            data.extend_from_slice(&(payload.len() as u64 + 16).to_be_bytes());
+            data.extend_from_slice(payload);
+            return data;
+        }
+    }
+    data.extend_from_slice(kind);
+    data.extend_from_slice(payload);
+    data
+}
+
+/// 重建 moov/udta/meta/ilst，保留已有的其他元数据项
+fn rebuild_moov(moov: &[u8], items: &[([u8; 4], String)]) -> Result<Vec<u8>> {
+    let moov_box = child_boxes(moov)?.pop().ok_or_else(|| invalid("MP4"))?;
+    let children = child_boxes(moov_box.payload())?;
+    let udta = children.iter().find(|b| &b.kind == b"udta");
+
+    let mut payload = Vec::with_capacity(moov.len() + 1024);
+    for child in children.iter().filter(|b| &b.kind != b"udta") {
+        payload.extend_from_slice(child.raw);
+    }
+    payload.extend_from_slice(&rebuild_udta(udta.map(Mp4Box::payload), items)?);
+    Ok(make_box(b"moov", &payload))
+}
+
+fn rebuild_udta(udta: Option<&[u8]>, items: &[([u8; 4], String)]) -> Result<Vec<u8>> {
+    let children = udta.map(child_boxes).transpose()?.unwrap_or_default();
+    // meta 是 FullBox，子 box 之前有 4 字节的版本和标志
+    let meta = children
+        .iter()
+        .find(|b| &b.kind == b"meta")
+        .and_then(|b| b.payload().get(4..));
+    let meta_children = meta.map(child_boxes).transpose()?.unwrap_or_default();
+    let ilst = meta_children.iter().find(|b| &b.kind == b"ilst");
+    let ilst_children = ilst
+        .map(|b| child_boxes(b.payload()))
+        .transpose()?
+        .unwrap_or_default();
+
+    let mut ilst_payload = Vec::new();
+    for item in ilst_children
+        .iter()
+        .filter(|b| !items.iter().any(|(kind, _)| *kind == b.kind))
+    {
+        ilst_payload.extend_from_slice(item.raw);
+    }
+    for (kind, value) in items {
+        // data box：类型 1 表示 UTF-8 文本，之后是 4 字节的区域设置
+        let mut data = vec![0, 0, 0, 1, 0, 0, 0, 0];
+        data.extend_from_slice(value.as_bytes());
+        ilst_payload.extend_from_slice(&make_box(kind, &make_box(b"data", &data)));
+    }
+
+    let mut meta_payload = vec![0, 0, 0, 0];
+    if !meta_children.iter().any(|b| &b.kind == b"hdlr") {
+        let mut hdlr = vec![0u8; 8];
+        hdlr.extend_from_slice(b"mdirappl");
+        hdlr.extend_from_slice(&[0u8; 9]);
+        meta_payload.extend_from_slice(&make_box(b"hdlr", &hdlr));
+    }
+    for child in meta_children.iter().filter(|b| &b.kind != b"ilst") {
+        meta_payload.extend_from_slice(child.raw);
+    }
+    meta_payload.extend_from_slice(&make_box(b"ilst", &ilst_payload));
+
+    let mut udta_payload = Vec::new();
+    for child in children.iter().filter(|b| &b.kind != b"meta") {
+        udta_payload.extend_from_slice(child.raw);
+    }
+    udta_payload.extend_from_slice(&make_box(b"meta", &meta_payload));
+    Ok(make_box(b"udta", &udta_payload))
+}
+
+/// 调整 stco/co64 中位于原 moov 之后的数据块偏移
+fn shift_chunk_offsets(moov: &mut [u8], moov_end: u64, delta: i64) -> Result<()> {
+    if delta == 0 {
+        return Ok(());
+    }
+    let mut tables = Vec::new();
+    find_chunk_offset_tables(moov, 0, &mut tables)?;
+
+    for (kind, start, end) in tables {
+        let table = &mut moov[start..end];
+        let count = table
+            .get(4..8)
+            .map(|b| u32::from_be_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(|| invalid("MP4"))?;
+        let width = if &kind == b"co64" { 8 } else { 4 };
+        let entries = table
+            .get_mut(8..8 + count * width)
+            .ok_or_else(|| invalid("MP4"))?;
+        for entry in entries.chunks_exact_mut(width) {
+            let offset = if width == 8 {
+                u64::from_be_bytes(entry.try_into().unwrap())
+            } else {
+                u32::from_be_bytes(entry.try_into().unwrap()) as u64
+            };
+            if offset < moov_end {
+                continue;
+            }
+            let shifted = offset
+                .checked_add_signed(delta)
+                .ok_or_else(|| invalid("MP4"))?;
+            if width == 8 {
+                entry.copy_from_slice(&shifted.to_be_bytes());
+            } else {
+                let shifted =
+                    u32::try_from(shifted).map_err(|_| anyhow!("MP4 数据偏移超出 stco 范围"))?;
+                entry.copy_from_slice(&shifted.to_be_bytes());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 递归查找 stco/co64 的载荷在 data 中的范围
+fn find_chunk_offset_tables(
+    data: &[u8],
+    base: usize,
+    tables: &mut Vec<([u8; 4], usize, usize)>,
+) -> Result<()> {
+    for child in child_boxes(data)? {
+        let start = base + child.offset;
+        let payload_start = start + child.header_len;
+        match &child.kind {
+            b"moov" | b"trak" | b"mdia" | b"minf" | b"stbl" => {
+                find_chunk_offset_tables(child.payload(), payload_start, tables)?
+            }
+            b"stco" | b"co64" => tables.push((child.kind, payload_start, start + child.raw.len())),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[path = "embed_test.rs"]
+mod tests;
@@ -0,0 +1,174 @@
+use super::*;
+
+fn metadata() -> Sidecar {
+    Sidecar {
+        tweet_id: "100".to_string(),
+        tweet_url: "https://x.com/alice/status/100".to_string(),
+        author_id: Some("42".to_string()),
+        screen_name: Some("alice".to_string()),
+        display_name: Some("Alice".to_string()),
+        text: Some("猫 & <狗>".to_string()),
+        created_at: Some("2017-04-06T15:24:15+00:00".to_string()),
+        alt_text: None,
+        media_url: "https://pbs.twimg.com/media/a.jpg".to_string(),
+    }
+}
+
+fn count(haystack: &[u8], needle: &[u8]) -> usize {
+    haystack
+        .windows(needle.len())
+        .filter(|w| *w == needle)
+        .count()
+}
+
+#[test]
+fn test_xmp_packet_escapes_text() {
+    let xmp = xmp_packet(&metadata());
+    assert!(xmp.contains("<rdf:li>Alice (@alice)</rdf:li>"));
+    assert!(xmp.contains(">猫 &amp; &lt;狗&gt;</rdf:li>"));
+    assert!(xmp.contains("<dc:source>https://x.com/alice/status/100</dc:source>"));
+    assert!(xmp.contains("<xmp:CreateDate>2017-04-06T15:24:15+00:00</xmp:CreateDate>"));
+}
+
+#[test]
+fn test_jpeg_xmp_truncates_long_text() {
+    // 长推文的中文正文超过一个 APP1 段的长度
+    let long = Sidecar {
+        text: Some("猫&".repeat(20000)),
+        ..metadata()
+    };
+    assert!(xmp_packet(&long).len() > JPEG_XMP_MAX_LEN);
+    let xmp = jpeg_xmp_packet(&long);
+    assert!(xmp.len() <= JPEG_XMP_MAX_LEN);
+    assert!(xmp.len() > JPEG_XMP_MAX_LEN - 8);
+    assert!(xmp.contains("猫&amp;…</rdf:li>") || xmp.contains("&amp;猫…</rdf:li>"));
+    assert!(xmp.contains("<dc:source>https://x.com/alice/status/100</dc:source>"));
+
+    let mut jpeg = vec![0xFF, 0xD8];
+    jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x04, 0x01, 0x02, 0xFF, 0xD9]);
+    assert!(embed_jpeg(&jpeg, &xmp).is_ok());
+
+    // 不超出时保持原样
+    assert_eq!(jpeg_xmp_packet(&metadata()), xmp_packet(&metadata()));
+}
+
+#[test]
+fn test_embed_jpeg_and_png_replace_existing_xmp() {
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x07];
+    jpeg.extend_from_slice(b"JFIF\0");
+    jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x04, 0x01, 0x02, 0x12, 0x34, 0xFF, 0xD9]);
+
+    let once = embed_jpeg(&jpeg, "<a/>").unwrap();
+    let twice = embed_jpeg(&once, "<b/>").unwrap();
+    assert_eq!(count(&twice, JPEG_XMP_NAMESPACE), 1);
+    // XMP 段位于 JFIF 段之后，压缩数据原样保留
+    assert_eq!(&twice[..11], &jpeg[..11]);
+    assert_eq!(&twice[11..13], &[0xFF, 0xE1]);
+    assert!(twice.ends_with(&jpeg[11..]));
+    assert!(count(&twice, b"<b/>") == 1 && count(&twice, b"<a/>") == 0);
+
+    assert_eq!(
+        png_chunk(b"IEND", &[]),
+        [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]
+    );
+    let mut png = PNG_SIGNATURE.to_vec();
+    png.extend_from_slice(&png_chunk(b"IHDR", &[0; 13]));
+    png.extend_from_slice(&png_chunk(b"IEND", &[]));
+
+    let twice = embed_png(&embed_png(&png, "<a/>").unwrap(), "<b/>").unwrap();
+    assert_eq!(count(&twice, PNG_XMP_KEYWORD), 1);
+    assert_eq!(&twice[33 + 4..33 + 8], b"iTXt");
+    assert!(twice.ends_with(&png_chunk(b"IEND", &[])));
+}
+
+#[test]
+fn test_embed_webp_converts_simple_format() {
+    // 100x50、带透明通道的 VP8L，载荷长度为奇数，需要填充
+    let bits: u32 = 99 | (49 << 14) | (1 << 28);
+    let mut vp8l = vec![0x2F];
+    vp8l.extend_from_slice(&bits.to_le_bytes());
+    let mut webp = b"RIFF".to_vec();
+    webp.extend_from_slice(&18u32.to_le_bytes());
+    webp.extend_from_slice(b"WEBPVP8L");
+    webp.extend_from_slice(&5u32.to_le_bytes());
+    webp.extend_from_slice(&vp8l);
+    webp.push(0);
+
+    let output = embed_webp(&webp, "<x/>").unwrap();
+    assert_eq!(
+        u32::from_le_bytes(output[4..8].try_into().unwrap()) as usize,
+        output.len() - 8
+    );
+    assert_eq!(&output[12..20], b"VP8X\x0A\0\0\0");
+    assert_eq!(&output[20..30], &[0x14, 0, 0, 0, 99, 0, 0, 49, 0, 0]);
+    assert_eq!(&output[30..38], b"VP8L\x05\0\0\0");
+    assert!(output.ends_with(b"XMP \x04\0\0\0<x/>"));
+
+    // 已是扩展格式时只替换 XMP 块
+    let again = embed_webp(&output, "<y/>").unwrap();
+    assert_eq!(again.len(), output.len());
+    assert!(again.ends_with(b"XMP \x04\0\0\0<y/>"));
+}
+
+#[test]
+fn test_embed_webp_rejects_zero_size_vp8() {
+    // 宽为 0 的有损 VP8 数据
+    let mut vp8 = vec![0, 0, 0, 0x9D, 0x01, 0x2A];
+    vp8.extend_from_slice(&0u16.to_le_bytes());
+    vp8.extend_from_slice(&50u16.to_le_bytes());
+    let mut webp = b"RIFF".to_vec();
+    webp.extend_from_slice(&22u32.to_le_bytes());
+    webp.extend_from_slice(b"WEBPVP8 ");
+    webp.extend_from_slice(&10u32.to_le_bytes());
+    webp.extend_from_slice(&vp8);
+
+    let err = embed_webp(&webp, "<x/>").unwrap_err();
+    assert!(err.to_string().contains("WebP"));
+}
+
+/// moov 在 mdat 之前，stco 指向 mdat 中的数据
+fn sample_mp4() -> Vec<u8> {
+    let ftyp = make_box(b"ftyp", b"isom\0\0\0\0");
+    let moov_len = |offset: u32| {
+        let mut stco = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stco.extend_from_slice(&offset.to_be_bytes());
+        let stbl = make_box(b"stbl", &make_box(b"stco", &stco));
+        let trak = make_box(b"trak", &make_box(b"mdia", &make_box(b"minf", &stbl)));
+        let mut payload = make_box(b"mvhd", &[0; 8]);
+        payload.extend_from_slice(&trak);
+        make_box(b"moov", &payload)
+    };
+    let moov_size = moov_len(0).len() as u32;
+    let offset = ftyp.len() as u32 + moov_size + 8;
+
+    let mut data = ftyp;
+    data.extend_from_slice(&moov_len(offset));
+    data.extend_from_slice(&make_box(b"mdat", b"hello"));
+    data
+}
+
+fn stco_offset(data: &[u8]) -> usize {
+    let pos = data.windows(4).position(|w| w == b"stco").unwrap();
+    u32::from_be_bytes(data[pos + 12..pos + 16].try_into().unwrap()) as usize
+}
+
+#[test]
+fn test_embed_mp4_shifts_chunk_offsets() {
+    let path = std::env::temp_dir().join(format!(
+        "x_likes_downloader_{}_embed.mp4",
+        std::process::id()
+    ));
+    fs::write(&path, sample_mp4()).unwrap();
+
+    assert!(embed_metadata(&path, &metadata()).unwrap());
+    assert!(embed_metadata(&path, &metadata()).unwrap());
+    let data = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let offset = stco_offset(&data);
+    assert_eq!(&data[offset..offset + 5], b"hello");
+    assert_eq!(count(&data, b"\xA9cmt"), 1);
+    assert_eq!(count(&data, b"https://x.com/alice/status/100"), 1);
+    assert_eq!(count(&data, "Alice (@alice)".as_bytes()), 1);
+    assert_eq!(count(&data, b"mdirappl"), 1);
+}
@@ -0,0 +1,180 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use url::Url;
+
+use crate::downloader::VideoPolicy;
+
+/// 主播放列表中的一个清晰度
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub uri: Url,
+    pub bandwidth: u64,
+    pub resolution: Option<(u64, u64)>,
+    /// 独立音轨的播放列表，视频与音频分开时才有
+    pub audio: Option<Url>,
+}
+
+/// 媒体播放列表，分片按播放顺序排列
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaPlaylist {
+    /// fMP4 的初始化分片（EXT-X-MAP），MPEG-TS 没有
+    pub init: Option<Url>,
+    pub segments: Vec<Url>,
+}
+
+impl MediaPlaylist {
+    /// 拼接后的文件扩展名：fMP4 分片拼成 mp4，TS 分片拼成 ts
+    pub fn extension(&self) -> &'static str {
+        if self.init.is_some() {
+            "mp4"
+        } else {
+            "ts"
+        }
+    }
+}
+
+/// 是否为主播放列表（包含多个清晰度）
+pub fn is_master(text: &str) -> bool {
+    text.contains("#EXT-X-STREAM-INF")
+}
+
+/// 解析主播放列表中的各清晰度，音轨按 GROUP-ID 关联到对应清晰度
+pub fn parse_master(text: &str, base: &Url) -> Result<Vec<Variant>> {
+    let mut audio_groups = HashMap::new();
+    for line in text.lines() {
+        if let Some(attrs) = line.trim().strip_prefix("#EXT-X-MEDIA:") {
+            let attrs = parse_attributes(attrs);
+            if let (Some("AUDIO"), Some(group), Some(uri)) = (
+                attrs.get("TYPE").map(String::as_str),
+                attrs.get("GROUP-ID"),
+                attrs.get("URI"),
+            ) {
+                audio_groups.insert(group.clone(), base.join(uri)?);
+            }
+        }
+    }
+
+    let mut variants = Vec::new();
+    let mut lines = text.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        let Some(attrs) = line.strip_prefix("#EXT-X-STREAM-INF:") else {
+            continue;
+        };
+        let attrs = parse_attributes(attrs);
+        let uri = lines
+            .find(|l| !l.is_empty() && !l.starts_with('#'))
+            .ok_or_else(|| anyhow!("EXT-X-STREAM-INF 之后缺少播放列表地址"))?;
+
+        variants.push(Variant {
+            uri: base.join(uri)?,
+            bandwidth: attrs
+                .get("BANDWIDTH")
+                .and_then(|b| b.parse().ok())
+                .unwrap_or(0),
+            resolution: attrs.get("RESOLUTION").and_then(|r| {
+                let (width, height) = r.split_once('x')?;
+                Some((width.parse().ok()?, height.parse().ok()?))
+            }),
+            audio: attrs
+                .get("AUDIO")
+                .and_then(|group| audio_groups.get(group))
+                .cloned(),
+        });
+    }
+
+    Ok(variants)
+}
+
+/// 解析媒体播放列表，不支持加密分片
+pub fn parse_media(text: &str, base: &Url) -> Result<MediaPlaylist> {
+    let mut init = None;
+    let mut segments = Vec::new();
+
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(attrs) = line.strip_prefix("#EXT-X-MAP:") {
+            if let Some(uri) = parse_attributes(attrs).get("URI") {
+                init = Some(base.join(uri)?);
+            }
+        } else if let Some(attrs) = line.strip_prefix("#EXT-X-KEY:") {
+            let method = parse_attributes(attrs).remove("METHOD").unwrap_or_default();
+            if method != "NONE" {
+                return Err(anyhow!("不支持加密的 HLS 分片 (METHOD={})", method));
+            }
+        } else if !line.starts_with('#') {
+            segments.push(base.join(line)?);
+        }
+    }
+
+    if segments.is_empty() {
+        return Err(anyhow!("HLS 播放列表中没有分片"));
+    }
+    Ok(MediaPlaylist { init, segments })
+}
+
+/// 按视频清晰度策略选择，与 mp4 变体的选择规则一致：
+/// 分辨率上限按短边比较，大小上限按带宽和时长估算，没有满足上限的清晰度时退回带宽最低的
+pub fn select_variant<'a>(
+    variants: &'a [Variant],
+    duration_ms: Option<u64>,
+    policy: &VideoPolicy,
+) -> Option<&'a Variant> {
+    let lowest = || variants.iter().min_by_key(|v| v.bandwidth);
+    let highest = || variants.iter().max_by_key(|v| v.bandwidth);
+
+    match *policy {
+        VideoPolicy::Highest => highest(),
+        VideoPolicy::Lowest => lowest(),
+        VideoPolicy::MaxResolution(max_height) => variants
+            .iter()
+            .filter_map(|v| {
+                let (width, height) = v.resolution?;
+                (width.min(height) <= max_height).then_some((width * height, v))
+            })
+            .max_by_key(|(pixels, v)| (*pixels, v.bandwidth))
+            .map(|(_, v)| v)
+            .or_else(lowest),
+        VideoPolicy::MaxSizeMb(max_mb) => {
+            let Some(duration_ms) = duration_ms else {
+                return highest();
+            };
+            variants
+                .iter()
+                .filter(|v| v.bandwidth / 8 * duration_ms / 1000 <= max_mb * 1024 * 1024)
+                .max_by_key(|v| v.bandwidth)
+                .or_else(lowest)
+        }
+    }
+}
+
+/// 解析 `KEY=VALUE,KEY="VALUE"` 形式的属性列表，引号内的逗号不作为分隔符
+fn parse_attributes(attrs: &str) -> HashMap<String, String> {
+    let mut result = HashMap::new();
+    let mut rest = attrs.trim();
+
+    while !rest.is_empty() {
+        let Some((key, after_key)) = rest.split_once('=') else {
+            break;
+        };
+        let (value, after_value) = match after_key.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                let after = quoted.get(end + 1..).unwrap_or("");
+                (&quoted[..end], after)
+            }
+            None => after_key
+                .split_once(',')
+                .map_or((after_key, ""), |(v, a)| (v, a)),
+        };
+        result.insert(key.trim().to_string(), value.to_string());
+        rest = after_value.trim_start_matches(',').trim();
+    }
+
+    result
+}
+
+#[cfg(test)]
+#[path = "hls_test.rs"]
+mod tests;
@@ -0,0 +1,85 @@
+use super::*;
+
+const MASTER: &str = r#"#EXTM3U
+#EXT-X-INDEPENDENT-SEGMENTS
+#EXT-X-MEDIA:NAME="Audio",TYPE=AUDIO,GROUP-ID="audio-128000",AUTOSELECT=YES,URI="/ext_tw_video/1/pu/pl/mp4a/128000/audio.m3u8"
+#EXT-X-STREAM-INF:AVERAGE-BANDWIDTH=300000,BANDWIDTH=400000,RESOLUTION=480x270,CODECS="mp4a.40.2,avc1.4D401E",AUDIO="audio-128000"
+/ext_tw_video/1/pu/pl/avc1/480x270/low.m3u8
+#EXT-X-STREAM-INF:AVERAGE-BANDWIDTH=2000000,BANDWIDTH=2500000,RESOLUTION=1280x720,CODECS="mp4a.40.2,avc1.640020",AUDIO="audio-128000"
+/ext_tw_video/1/pu/pl/avc1/1280x720/mid.m3u8
+#EXT-X-STREAM-INF:AVERAGE-BANDWIDTH=8000000,BANDWIDTH=10000000,RESOLUTION=1920x1080,CODECS="mp4a.40.2,avc1.640028",AUDIO="audio-128000"
+/ext_tw_video/1/pu/pl/avc1/1920x1080/high.m3u8
+"#;
+
+fn base() -> Url {
+    Url::parse("https://video.twimg.com/ext_tw_video/1/pu/pl/master.m3u8?tag=12").unwrap()
+}
+
+#[test]
+fn test_parse_master_variants_and_audio() {
+    assert!(is_master(MASTER));
+    let variants = parse_master(MASTER, &base()).unwrap();
+
+    assert_eq!(variants.len(), 3);
+    assert_eq!(variants[1].bandwidth, 2500000);
+    assert_eq!(variants[1].resolution, Some((1280, 720)));
+    assert_eq!(
+        variants[1].uri.as_str(),
+        "https://video.twimg.com/ext_tw_video/1/pu/pl/avc1/1280x720/mid.m3u8"
+    );
+    assert_eq!(
+        variants[1].audio.as_ref().map(Url::as_str),
+        Some("https://video.twimg.com/ext_tw_video/1/pu/pl/mp4a/128000/audio.m3u8")
+    );
+}
+
+#[test]
+fn test_select_variant_follows_video_policy() {
+    let variants = parse_master(MASTER, &base()).unwrap();
+    let pick = |policy: VideoPolicy, duration_ms| {
+        select_variant(&variants, duration_ms, &policy)
+            .unwrap()
+            .resolution
+            .unwrap()
+            .1
+    };
+
+    assert_eq!(pick(VideoPolicy::Highest, None), 1080);
+    assert_eq!(pick(VideoPolicy::Lowest, None), 270);
+    assert_eq!(pick(VideoPolicy::MaxResolution(720), None), 720);
+    // 60 秒：1080p 约 71MB，720p 约 18MB
+    assert_eq!(pick(VideoPolicy::MaxSizeMb(50), Some(60_000)), 720);
+}
+
+#[test]
+fn test_parse_fmp4_media_playlist() {
+    let text = "#EXTM3U\n#EXT-X-TARGETDURATION:3\n#EXT-X-MAP:URI=\"/v/init.mp4\"\n\
+                #EXTINF:3.000,\n/v/0/3000/a.m4s\n#EXTINF:1.500,\n/v/3000/4500/b.m4s\n#EXT-X-ENDLIST\n";
+    let playlist = parse_media(text, &base()).unwrap();
+
+    assert_eq!(
+        playlist.init.as_ref().map(Url::as_str),
+        Some("https://video.twimg.com/v/init.mp4")
+    );
+    assert_eq!(playlist.segments.len(), 2);
+    assert_eq!(playlist.extension(), "mp4");
+}
+
+#[test]
+fn test_parse_ts_media_playlist_relative_segments() {
+    let text = "#EXTM3U\n#EXTINF:3.0,\nseg0.ts\n#EXTINF:3.0,\nseg1.ts\n";
+    let playlist = parse_media(text, &base()).unwrap();
+
+    assert_eq!(
+        playlist.segments[1].as_str(),
+        "https://video.twimg.com/ext_tw_video/1/pu/pl/seg1.ts"
+    );
+    assert_eq!(playlist.extension(), "ts");
+}
+
+#[test]
+fn test_parse_media_rejects_encrypted_and_empty() {
+    let encrypted = "#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"key\"\n#EXTINF:3,\na.ts\n";
+    assert!(parse_media(encrypted, &base()).is_err());
+    assert!(parse_media("#EXTM3U\n#EXT-X-ENDLIST\n", &base()).is_err());
+}
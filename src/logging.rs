@@ -0,0 +1,125 @@
+use anyhow::{Context, Result};
+use indicatif::MultiProgress;
+use std::env;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::OnceLock;
+use tracing::{Event, Level, Subscriber};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::EnvFilter;
+
+static PROGRESS: OnceLock<MultiProgress> = OnceLock::new();
+
+/// 所有进度条共用的 MultiProgress。终端日志输出时会先暂停进度条，避免日志行与进度条交错
+pub fn progress() -> &'static MultiProgress {
+    PROGRESS.get_or_init(MultiProgress::new)
+}
+
+/// 初始化日志：终端按 -v/-q 或 RUST_LOG 过滤，设置 LOG_DIR 时同时按天写入日志文件
+///
+/// 日志在加载配置之前初始化（setup 命令还没有令牌文件），因此直接读取环境变量。
+pub fn init(verbose: u8, quiet: u8) -> Result<()> {
+    let directives = filter_directives(verbose, quiet, env::var("RUST_LOG").ok().as_deref());
+    let filter = EnvFilter::try_new(&directives)
+        .with_context(|| format!("无效的日志级别: {}", directives))?;
+
+    let console = tracing_subscriber::fmt::layer()
+        .event_format(ConsoleFormat)
+        .with_writer(|| ConsoleWriter);
+
+    let log_dir = env::var("LOG_DIR").unwrap_or_default();
+    let file = if log_dir.trim().is_empty() {
+        None
+    } else {
+        let max_files = env::var("LOG_MAX_FILES")
+            .unwrap_or_else(|_| "7".to_string())
+            .parse::<usize>()
+            .context("LOG_MAX_FILES 必须是正整数")?;
+        let appender = RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix("x_likes_downloader")
+            .filename_suffix("log")
+            .max_log_files(max_files.max(1))
+            .build(log_dir.trim())
+            .with_context(|| format!("无法创建日志目录: {}", log_dir))?;
+        Some(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_target(false)
+                .with_writer(appender),
+        )
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(console)
+        .with(file)
+        .try_init()
+        .context("初始化日志失败")
+}
+
+/// 日志过滤规则：-v/-q 优先，其次是 RUST_LOG，默认 info。按次数指定时其他库只输出警告
+fn filter_directives(verbose: u8, quiet: u8, rust_log: Option<&str>) -> String {
+    let level = match (verbose, quiet) {
+        (0, 0) => {
+            return match rust_log.map(str::trim).filter(|s| !s.is_empty()) {
+                Some(directives) => directives.to_string(),
+                None => format!("warn,{}=info", env!("CARGO_CRATE_NAME")),
+            }
+        }
+        (0, 1) => "warn",
+        (0, _) => "error",
+        (1, _) => "debug",
+        _ => "trace",
+    };
+    format!("warn,{}={}", env!("CARGO_CRATE_NAME"), level)
+}
+
+/// 终端输出格式：info 只输出消息本身，其他级别加上中文前缀
+struct ConsoleFormat;
+
+impl<S, N> FormatEvent<S, N> for ConsoleFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let prefix = match *event.metadata().level() {
+            Level::ERROR => "错误: ",
+            Level::WARN => "警告: ",
+            Level::INFO => "",
+            Level::DEBUG => "[调试] ",
+            Level::TRACE => "[跟踪] ",
+        };
+        write!(writer, "{}", prefix)?;
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// 写入标准输出前暂停进度条，写完后重新绘制
+struct ConsoleWriter;
+
+impl Write for ConsoleWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        progress().suspend(|| io::stdout().write_all(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+#[cfg(test)]
+#[path = "logging_test.rs"]
+mod tests;
@@ -0,0 +1,32 @@
+use super::*;
+
+#[test]
+fn test_filter_directives_flags_override_rust_log() {
+    assert_eq!(
+        filter_directives(0, 0, None),
+        "warn,x_likes_downloader=info"
+    );
+    assert_eq!(filter_directives(0, 0, Some("debug")), "debug");
+    assert_eq!(
+        filter_directives(0, 0, Some(" ")),
+        "warn,x_likes_downloader=info"
+    );
+
+    // -v/-q 优先于 RUST_LOG
+    assert_eq!(
+        filter_directives(1, 0, Some("error")),
+        "warn,x_likes_downloader=debug"
+    );
+    assert_eq!(
+        filter_directives(2, 0, None),
+        "warn,x_likes_downloader=trace"
+    );
+    assert_eq!(
+        filter_directives(0, 1, Some("debug")),
+        "warn,x_likes_downloader=warn"
+    );
+    assert_eq!(
+        filter_directives(0, 2, None),
+        "warn,x_likes_downloader=error"
+    );
+}
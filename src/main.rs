@@ -1,19 +1,37 @@
+mod catalog;
 mod config;
+mod crawl_state;
 mod downloader;
+mod embed;
+mod hls;
+mod logging;
+mod models;
+mod mux;
 mod organize_files;
+mod proxy;
+mod rate_limit;
 mod setup;
+mod sidecar;
 mod updater;
 mod x_api;
 
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use anyhow::{Context, Result};
+use clap::builder::PossibleValue;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use futures_util::{stream, StreamExt};
+use std::fs;
+use std::path::Path;
 
 use config::Config;
+use crawl_state::{Checkpoint, CheckpointStore, CrawlState};
 use downloader::Downloader;
+use models::{Entry, Tweet, TweetResult};
 use organize_files::FileOrganizer;
+use proxy::ProxySettings;
 use setup::SetupArgs;
+use tracing::{error, info, warn};
 use updater::Updater;
-use x_api::XApi;
+use x_api::{AuthError, IncrementalStop, Timeline, XApi};
 
 #[derive(Parser)]
 #[command(name = "x_likes_downloader")]
@@ -22,14 +40,28 @@ use x_api::XApi;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// 输出更详细的日志（-v 为 debug，-vv 为 trace），优先于 RUST_LOG
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// 减少日志输出（-q 只输出警告和错误，-qq 只输出错误）
+    #[arg(short, long, action = clap::ArgAction::Count, global = true, conflicts_with = "verbose")]
+    quiet: u8,
+
+    /// 忽略 PROXY、HTTPS_PROXY、SOCKS5 和系统代理设置，直接连接
+    #[arg(long, global = true)]
+    no_proxy: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// 初始化配置
     Setup(SetupArgs),
-    /// 下载点赞的推文媒体
-    Download,
+    /// 下载点赞、书签、用户媒体或指定推文中的媒体
+    Download(DownloadArgs),
+    /// 检查保存的登录令牌是否仍然有效
+    CheckAuth,
     /// 整理下载的文件
     Organize {
         /// 源目录
@@ -41,16 +73,74 @@ enum Commands {
     Update,
 }
 
+#[derive(Args, Clone)]
+struct DownloadArgs {
+    /// 下载来源
+    #[arg(long, value_enum, default_value_t = Timeline::Likes)]
+    source: Timeline,
+
+    /// 下载指定用户的媒体时间线（用户名，可带 @ 前缀）
+    #[arg(long, value_name = "SCREEN_NAME", conflicts_with = "source")]
+    user: Option<String>,
+
+    /// 下载指定推文（推文 URL、ID，或每行一个 URL 的文件）
+    #[arg(
+        long,
+        value_name = "URL_OR_ID",
+        num_args = 1..,
+        conflicts_with_all = ["source", "user"]
+    )]
+    tweet: Vec<String>,
+
+    /// 增量模式：遇到已下载过的推文后停止翻页（也可通过 INCREMENTAL=true 开启）
+    #[arg(long)]
+    incremental: bool,
+
+    /// 从上次中断时保存的检查点继续翻页
+    #[arg(long, conflicts_with = "tweet")]
+    resume: bool,
+
+    /// 重新处理已下载的推文，将尺寸低于 PHOTO_QUALITY 的图片重新下载（不使用增量模式）
+    #[arg(long, conflicts_with = "incremental")]
+    upgrade: bool,
+}
+
+/// --source 只能选择点赞或书签，用户媒体时间线通过 --user 指定
+impl ValueEnum for Timeline {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Timeline::Likes, Timeline::Bookmarks]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        match self {
+            Timeline::Likes => Some(PossibleValue::new("likes").help("点赞")),
+            Timeline::Bookmarks => Some(PossibleValue::new("bookmarks").help("书签")),
+            Timeline::UserMedia { .. } => None,
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    dotenv::dotenv().ok();
+    logging::init(cli.verbose, cli.quiet)?;
 
     match &cli.command {
         Commands::Setup(args) => {
             setup::run_setup(args.clone())?;
         }
-        Commands::Download => {
-            run_download().await?;
+        Commands::Download(args) => {
+            run_download(load_config(&cli)?, args.clone()).await?;
+        }
+        Commands::CheckAuth => {
+            let config = load_config(&cli)?;
+            if config.mock_mode {
+                info!("Mock 模式下不检查登录令牌");
+                return Ok(());
+            }
+            XApi::new(config)?.check_auth().await?;
+            info!("登录令牌有效");
         }
         Commands::Organize {
             source_dir,
@@ -82,7 +172,9 @@ async fn main() -> Result<()> {
             FileOrganizer::organize_files(src, tgt)?;
         }
         Commands::Update => {
-            let updater = Updater::new()?;
+            let mut proxy = ProxySettings::from_env();
+            proxy.no_proxy = cli.no_proxy;
+            let updater = Updater::new(&proxy)?;
             updater.update().await?;
         }
     }
@@ -90,92 +182,341 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-async fn run_download() -> Result<()> {
-    // 加载配置
-    let config = Config::load()?;
+/// 加载配置，--no-proxy 时忽略代理设置
+fn load_config(cli: &Cli) -> Result<Config> {
+    let mut config = Config::load()?;
+    config.proxy.no_proxy |= cli.no_proxy;
+    Ok(config)
+}
 
+async fn run_download(config: Config, args: DownloadArgs) -> Result<()> {
     // 创建API客户端
     let api = XApi::new(config.clone())?;
 
-    // 创建下载器
-    let mut downloader = Downloader::new(config.clone())?;
+    if !args.tweet.is_empty() {
+        return run_tweet_download(&api, &config, &args.tweet).await;
+    }
 
-    // 获取点赞的推文
-    let tweets = match api.get_liked_tweets_internal().await {
-        Ok(tweets) => tweets,
-        Err(e) => {
-            println!("获取点赞 tweets 失败：{}", e);
-            return Ok(());
+    let timeline = match args.user {
+        Some(ref user) => {
+            let screen_name = user.trim_start_matches('@').to_string();
+            let user_id = api.resolve_user_id(&screen_name).await?;
+            info!("用户 @{} 的 ID: {}", screen_name, user_id);
+            Timeline::UserMedia {
+                user_id,
+                screen_name,
+            }
         }
+        None => args.source.clone(),
     };
 
-    println!("从 API 获取到 {} 条点赞的 tweet 数据", tweets.len());
-    println!("已记录的下载ID数量: {}", downloader.downloaded_count());
-
-    let mut processed_count = 0;
-    let mut download_success_count = 0;
-    let mut download_failed_count = 0;
-
-    let tweets_count = tweets.len();
-    for entry in tweets {
-        // 假设 tweet 数据结构中，rest_id 存储 tweet id
-        let tweet_data = entry
-            .get("content")
-            .and_then(|c| c.get("itemContent"))
-            .and_then(|ic| ic.get("tweet_results"))
-            .and_then(|tr| tr.get("result"))
-            .unwrap_or(&entry);
-
-        let tweet_id = tweet_data
-            .get("rest_id")
-            .and_then(|id| id.as_str())
-            .or_else(|| {
-                tweet_data
-                    .get("tweet")
-                    .and_then(|t| t.get("rest_id"))
-                    .and_then(|id| id.as_str())
-            });
-
-        if let Some(tweet_id) = tweet_id {
-            if downloader.is_downloaded(tweet_id) {
-                continue;
-            }
+    // 创建下载器，每个来源使用独立的下载记录
+    let downloader = Downloader::new(
+        config.clone(),
+        timeline.catalog_list(),
+        timeline.download_record(&config),
+    )?;
 
-            match downloader.call_media_downloader(tweet_data, tweet_id).await {
-                Ok(Some(true)) => {
-                    // 成功下载了媒体文件
-                    processed_count += 1;
-                    println!("处理 tweet ({}): {}", processed_count, tweet_id);
-                    downloader.save_downloaded_id(tweet_id)?;
-                    download_success_count += 1;
-                    println!("✓ 成功下载并记录tweet ID: {}", tweet_id);
-                }
-                Ok(Some(false)) => {
-                    // 实际尝试下载但失败了
-                    processed_count += 1;
-                    println!("处理 tweet ({}): {}", processed_count, tweet_id);
-                    download_failed_count += 1;
-                    println!("✗ 下载失败，不记录tweet ID: {}", tweet_id);
-                }
-                Ok(None) => {
-                    // 没有媒体文件，不显示任何日志，也不计数
+    let mut crawl_state = CrawlState::load(&config.crawl_state_file)?;
+    let mut checkpoints = CheckpointStore::load(&config.checkpoint_file)?;
+    let state_key = timeline.state_key();
+    let incremental_enabled = !args.upgrade && (args.incremental || config.incremental);
+
+    let resume_from = if args.resume {
+        match checkpoints.get(&state_key) {
+            Some(checkpoint) => {
+                info!(
+                    "从检查点继续：已抓取 {} 页，保存于 {}",
+                    checkpoint.pages, checkpoint.updated_at
+                );
+                if checkpoint.count != config.count || checkpoint.incremental != incremental_enabled
+                {
+                    info!(
+                        "注意：当前参数与检查点不同（检查点 COUNT={}，增量模式={}）",
+                        checkpoint.count, checkpoint.incremental
+                    );
                 }
-                Err(e) => {
-                    println!("处理 tweet {} 时发生错误: {}", tweet_id, e);
+                Some(checkpoint.clone())
+            }
+            None => {
+                info!("没有{}的检查点，从头开始抓取", timeline.label());
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // 从检查点恢复时位于较旧的位置，不按上次记录的位置停止
+    let mut incremental = incremental_enabled.then(|| {
+        let newest_sort_index = match resume_from {
+            Some(_) => None,
+            None => crawl_state.newest_sort_index(&state_key),
+        };
+        IncrementalStop::new(config.incremental_stop_after, newest_sort_index)
+    });
+
+    info!("已记录的下载ID数量: {}", downloader.downloaded_count());
+
+    // 逐页获取时间线中的推文，每页获取后立即下载
+    let start_cursor = resume_from.as_ref().map(|c| c.cursor.as_str());
+    let mut pages = Box::pin(api.timeline_pages(&timeline, start_cursor));
+    let mut summary = DownloadSummary::default();
+    let mut page_count = resume_from.as_ref().map_or(0, |c| c.pages);
+    let mut newest_sort_index = None;
+    let mut caught_up = false;
+    // 翻到了时间线末尾，与增量模式在已下载的位置停止不同，只有这时才能清除检查点
+    let mut exhausted = false;
+    let mut fetch_error = None;
+
+    while let Some(page) = pages.next().await {
+        let page = match page {
+            Ok(page) => page,
+            Err(e) => {
+                if checkpoints.get(&state_key).is_some() {
+                    info!("已获取的页面均已处理，可使用 download --resume 从检查点继续");
                 }
+                // 获取失败（包括会话失效）时不再继续，处理完已获取的页面后以非零状态退出
+                fetch_error = Some(if e.downcast_ref::<AuthError>().is_some() {
+                    e
+                } else {
+                    e.context(format!("获取{} tweets 失败", timeline.label()))
+                });
+                break;
+            }
+        };
+        page_count += 1;
+
+        newest_sort_index = page
+            .entries
+            .iter()
+            .filter_map(Entry::sort_index)
+            .chain(newest_sort_index)
+            .max();
+
+        // 必须在下载本页之前判断，否则本页的推文都会被视为已下载
+        let reached_known = incremental.as_mut().is_some_and(|stop| {
+            stop.reached_known(
+                &page.entries,
+                |id| downloader.is_downloaded(id),
+                |tweet| downloader.has_media(tweet),
+            )
+        });
+
+        download_entries(&downloader, page.entries, !args.upgrade, &mut summary).await?;
+
+        exhausted = page.next_cursor.is_none();
+        if reached_known || exhausted {
+            caught_up = true;
+            break;
+        }
+
+        // 本页已处理完，保存检查点，中断后可从下一页继续
+        if let (true, Some(cursor)) = (config.all, page.next_cursor) {
+            checkpoints.set(
+                &state_key,
+                Checkpoint {
+                    cursor,
+                    pages: page_count,
+                    count: config.count.clone(),
+                    incremental: incremental_enabled,
+                    updated_at: chrono::Local::now().to_rfc3339(),
+                },
+            );
+            checkpoints.save(&config.checkpoint_file)?;
+        }
+    }
+
+    downloader.overall_progress().finish_and_clear();
+    summary.print();
+
+    if let Some(e) = fetch_error {
+        return Err(e);
+    }
+
+    // 只有覆盖到时间线末尾或上次位置时才推进记录，避免增量模式跳过未抓取的部分
+    if caught_up {
+        if summary.failed > 0 {
+            // 否则下次增量运行会在失败的推文之前停止，失败的推文不再重试
+            info!("本次有下载失败的推文，不更新增量模式记录的位置");
+        } else if let Some(sort_index) = newest_sort_index {
+            crawl_state.update_newest_sort_index(&state_key, sort_index);
+            crawl_state.save(&config.crawl_state_file)?;
+        }
+    }
+
+    if exhausted && checkpoints.remove(&state_key) {
+        checkpoints.save(&config.checkpoint_file)?;
+        info!("{}时间线已抓取完毕，已清除检查点", timeline.label());
+    }
+
+    finish_download(&config)
+}
+
+/// 通过 TweetDetail 接口下载指定的推文，参数可以是推文 URL、ID 或每行一个 URL 的文件
+async fn run_tweet_download(api: &XApi, config: &Config, inputs: &[String]) -> Result<()> {
+    let mut tweet_ids = Vec::new();
+    for input in inputs {
+        let lines = if Path::new(input).is_file() {
+            fs::read_to_string(input)
+                .with_context(|| format!("无法读取文件: {}", input))?
+                .lines()
+                .map(|line| line.trim().to_string())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .collect()
+        } else {
+            vec![input.clone()]
+        };
+
+        for line in lines {
+            match x_api::parse_tweet_id(&line) {
+                Some(tweet_id) if !tweet_ids.contains(&tweet_id) => tweet_ids.push(tweet_id),
+                Some(_) => {}
+                None => warn!("无法识别的推文 URL 或 ID，跳过: {}", line),
+            }
+        }
+    }
+
+    let mut tweets = Vec::new();
+    let mut fetch_failed = 0;
+    for tweet_id in &tweet_ids {
+        match api.get_tweet_detail(tweet_id).await {
+            Ok(Some(entry)) => tweets.push(entry),
+            Ok(None) => warn!("TweetDetail 响应中找不到推文 {}", tweet_id),
+            Err(e) if e.downcast_ref::<AuthError>().is_some() => return Err(e),
+            Err(e) => {
+                error!("获取推文 {} 失败：{:#}", tweet_id, e);
+                fetch_failed += 1;
+            }
+        }
+    }
+
+    info!(
+        "从 API 获取到 {}/{} 条指定的 tweet 数据",
+        tweets.len(),
+        tweet_ids.len()
+    );
+
+    // 指定的推文总是重新尝试下载，已存在的文件会在下载器中跳过
+    let downloader = Downloader::new(config.clone(), "tweets", &config.tweets_download_record)?;
+    let mut summary = DownloadSummary::default();
+    download_entries(&downloader, tweets, false, &mut summary).await?;
+    downloader.overall_progress().finish_and_clear();
+    summary.print();
+    finish_download(config)?;
+
+    if fetch_failed > 0 {
+        return Err(anyhow::anyhow!("{} 条推文获取失败", fetch_failed));
+    }
+    Ok(())
+}
+
+/// 跨页累计的下载统计
+#[derive(Default)]
+struct DownloadSummary {
+    total: usize,
+    processed: usize,
+    success: usize,
+    failed: usize,
+    /// 已删除、被封禁或受限而无法获取的推文
+    unavailable: usize,
+}
+
+impl DownloadSummary {
+    fn print(&self) {
+        info!("\n=== 处理总结 ===");
+        info!("总tweet数量: {}", self.total);
+        info!("已处理数量: {}", self.processed);
+        info!("下载成功数量: {}", self.success);
+        info!("下载失败数量: {}", self.failed);
+        if self.unavailable > 0 {
+            info!("不可用数量: {}", self.unavailable);
+        }
+        info!("全部处理完成。");
+    }
+}
+
+/// 并发处理一批推文，并发数由 CONCURRENCY 控制
+async fn download_entries(
+    downloader: &Downloader,
+    tweets: Vec<Entry>,
+    skip_downloaded: bool,
+    summary: &mut DownloadSummary,
+) -> Result<()> {
+    summary.total += tweets.len();
+    let overall = downloader.overall_progress();
+    overall.inc_length(tweets.len() as u64);
+
+    let mut pending: Vec<(&Tweet, &str, Option<u128>)> = Vec::new();
+    for entry in &tweets {
+        let Some(tweet_id) = entry.tweet_id() else {
+            continue;
+        };
+        if skip_downloaded && downloader.is_downloaded(tweet_id) {
+            continue;
+        }
+
+        match entry.tweet_result() {
+            Some(TweetResult::Tweet(tweet_data)) => {
+                pending.push((tweet_data, tweet_id, entry.sort_index()))
             }
+            Some(TweetResult::Unavailable(reason)) => {
+                // 已删除或受限的推文写入记录文件，而不是静默跳过
+                summary.unavailable += 1;
+                warn!("tweet {} 不可用: {}", tweet_id, reason);
+                downloader.save_unavailable(tweet_id, reason)?;
+            }
+            None => warn!("tweet {} 缺少 tweet_results，跳过", tweet_id),
         }
     }
+    overall.inc((tweets.len() - pending.len()) as u64);
+
+    let mut results = stream::iter(pending)
+        .map(|(tweet_data, tweet_id, sort_index)| async move {
+            let result = downloader
+                .call_media_downloader(tweet_data, tweet_id, skip_downloaded)
+                .await;
+            overall.inc(1);
+            (tweet_id, sort_index, result)
+        })
+        .buffer_unordered(downloader.concurrency());
 
-    println!("\n=== 处理总结 ===");
-    println!("总tweet数量: {}", tweets_count);
-    println!("已处理数量: {}", processed_count);
-    println!("下载成功数量: {}", download_success_count);
-    println!("下载失败数量: {}", download_failed_count);
-    println!("全部处理完成。");
+    while let Some((tweet_id, sort_index, result)) = results.next().await {
+        match result {
+            Ok(Some(true)) => {
+                // 成功下载了媒体文件
+                summary.processed += 1;
+                info!("处理 tweet ({}): {}", summary.processed, tweet_id);
+                downloader.save_downloaded_id(tweet_id, sort_index).await?;
+                summary.success += 1;
+                info!("✓ 成功下载并记录tweet ID: {}", tweet_id);
+            }
+            Ok(Some(false)) => {
+                // 部分或全部媒体下载失败，已成功的媒体会单独记录
+                summary.processed += 1;
+                info!("处理 tweet ({}): {}", summary.processed, tweet_id);
+                summary.failed += 1;
+                warn!(
+                    "✗ 部分或全部媒体下载失败，不记录tweet ID，下次运行时重试: {}",
+                    tweet_id
+                );
+            }
+            Ok(None) => {
+                // 没有媒体文件，不显示任何日志，也不计数
+            }
+            Err(e) => {
+                summary.failed += 1;
+                error!("处理 tweet {} 时发生错误: {}", tweet_id, e);
+            }
+        }
+    }
+
+    Ok(())
+}
 
+fn finish_download(config: &Config) -> Result<()> {
     if config.auto_organize {
-        println!("开始整理下载的文件目录...");
+        info!("开始整理下载的文件目录...");
         FileOrganizer::organize_files(&config.download_dir, &config.target_dir)?;
     }
 
@@ -0,0 +1,454 @@
+use chrono::{DateTime, Utc};
+use serde::de::{DeserializeOwned, Error as _};
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+use tracing::warn;
+
+// X GraphQL 响应的数据结构。只声明用到的字段，推文的其余字段保存在 `extra` 中；
+// 必需字段缺失时反序列化会报错并指出字段名。条目列表逐条解析，
+// 个别条目格式异常时记录警告并跳过，不影响同一页的其它条目。
+
+/// Likes、UserMedia 响应：data.user.result.timeline_v2.timeline
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserTimelineResponse {
+    pub data: UserTimelineData,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserTimelineData {
+    pub user: UserTimelineUser,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserTimelineUser {
+    pub result: UserTimelineResult,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserTimelineResult {
+    #[serde(alias = "timeline")]
+    pub timeline_v2: TimelineWrapper,
+}
+
+/// Bookmarks 响应：data.bookmark_timeline_v2.timeline
+#[derive(Debug, Clone, Deserialize)]
+pub struct BookmarksResponse {
+    pub data: BookmarksData,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BookmarksData {
+    pub bookmark_timeline_v2: TimelineWrapper,
+}
+
+/// TweetDetail 响应：data.threaded_conversation_with_injections_v2
+#[derive(Debug, Clone, Deserialize)]
+pub struct TweetDetailResponse {
+    pub data: TweetDetailData,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TweetDetailData {
+    pub threaded_conversation_with_injections_v2: Timeline,
+}
+
+/// UserByScreenName 响应，用户不存在时 data.user 为空对象
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserByScreenNameResponse {
+    pub data: UserByScreenNameData,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserByScreenNameData {
+    #[serde(default)]
+    pub user: Option<UserResults>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TimelineWrapper {
+    pub timeline: Timeline,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Timeline {
+    pub instructions: Vec<Instruction>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type")]
+pub enum Instruction {
+    TimelineAddEntries {
+        #[serde(deserialize_with = "lenient_vec")]
+        entries: Vec<Entry>,
+    },
+    /// UserMedia 后续页向已有的媒体网格模块追加条目
+    TimelineAddToModule {
+        #[serde(rename = "moduleItems", deserialize_with = "lenient_vec")]
+        module_items: Vec<ModuleItem>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// 时间线条目：推文、cursor 或模块
+#[derive(Debug, Clone, Deserialize)]
+pub struct Entry {
+    #[serde(rename = "entryId")]
+    pub entry_id: String,
+    #[serde(rename = "sortIndex", default)]
+    pub sort_index: Option<String>,
+    #[serde(default)]
+    pub content: EntryContent,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EntryContent {
+    #[serde(rename = "itemContent", default)]
+    pub item_content: Option<ItemContent>,
+    /// 模块条目（如 UserMedia 首页的媒体网格）中的子条目
+    #[serde(default, deserialize_with = "lenient_option_vec")]
+    pub items: Option<Vec<ModuleItem>>,
+    /// cursor 条目的值
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModuleItem {
+    #[serde(rename = "entryId")]
+    pub entry_id: String,
+    pub item: ModuleItemBody,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModuleItemBody {
+    #[serde(rename = "itemContent", default)]
+    pub item_content: Option<ItemContent>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ItemContent {
+    #[serde(default)]
+    pub tweet_results: Option<TweetResults>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TweetResults {
+    #[serde(default)]
+    pub result: Option<TweetResult>,
+}
+
+/// 按 __typename 解析出的推文结果：受限或有年龄限制的账号返回 TweetWithVisibilityResults，
+/// 真正的推文在 `.tweet` 中；已删除或被封禁的返回 TweetTombstone
+#[derive(Debug, Clone)]
+pub enum TweetResult {
+    Tweet(Box<Tweet>),
+    /// 已删除、账号被封禁或受限而无法查看的推文，附带原因
+    Unavailable(String),
+}
+
+impl<'de> Deserialize<'de> for TweetResult {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let text_at = |pointer: &str, default: &str| {
+            value
+                .pointer(pointer)
+                .and_then(|t| t.as_str())
+                .unwrap_or(default)
+                .to_string()
+        };
+
+        // 没有 __typename 时按普通推文处理
+        match value.get("__typename").and_then(|t| t.as_str()) {
+            Some("Tweet") | None => Tweet::deserialize(value)
+                .map(|tweet| TweetResult::Tweet(Box::new(tweet)))
+                .map_err(D::Error::custom),
+            Some("TweetWithVisibilityResults") => {
+                let tweet = value
+                    .get("tweet")
+                    .ok_or_else(|| D::Error::missing_field("tweet"))?;
+                Tweet::deserialize(tweet)
+                    .map(|tweet| TweetResult::Tweet(Box::new(tweet)))
+                    .map_err(D::Error::custom)
+            }
+            Some("TweetTombstone") => Ok(TweetResult::Unavailable(text_at(
+                "/tombstone/text/text",
+                "推文已删除或不可见",
+            ))),
+            Some("TweetUnavailable") => {
+                Ok(TweetResult::Unavailable(text_at("/reason", "推文不可用")))
+            }
+            Some(other) => Ok(TweetResult::Unavailable(format!(
+                "未知的推文类型 {}",
+                other
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tweet {
+    pub rest_id: String,
+    #[serde(default)]
+    pub core: Option<TweetCore>,
+    pub legacy: TweetLegacy,
+    #[serde(default)]
+    pub quoted_status_result: Option<Box<TweetResults>>,
+    /// 未声明的字段，如 note_tweet
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Tweet {
+    /// 作者用户名，新版响应放在 user.core 中，旧版放在 user.legacy 中
+    pub fn screen_name(&self) -> Option<&str> {
+        let user = self.core.as_ref()?.user_results.result.as_ref()?;
+        user.core
+            .as_ref()
+            .and_then(|c| c.screen_name.as_deref())
+            .or_else(|| user.legacy.as_ref()?.screen_name.as_deref())
+    }
+
+    /// 作者的显示名称，与用户名一样新版在 user.core 中
+    pub fn display_name(&self) -> Option<&str> {
+        let user = self.core.as_ref()?.user_results.result.as_ref()?;
+        user.core
+            .as_ref()
+            .and_then(|c| c.name.as_deref())
+            .or_else(|| user.legacy.as_ref()?.name.as_deref())
+    }
+
+    /// 作者的用户 ID
+    pub fn author_id(&self) -> Option<&str> {
+        let user = self.core.as_ref()?.user_results.result.as_ref()?;
+        user.rest_id.as_deref()
+    }
+
+    /// 推文正文，长推文的完整正文在 note_tweet 中，legacy.full_text 只有截断后的部分
+    pub fn text(&self) -> Option<&str> {
+        self.extra
+            .get("note_tweet")
+            .and_then(|note| note.pointer("/note_tweet_results/result/text"))
+            .and_then(Value::as_str)
+            .or(self.legacy.full_text.as_deref())
+    }
+
+    /// 发布时间（Unix 秒），优先使用 created_at_ms
+    pub fn timestamp(&self) -> Option<i64> {
+        if let Some(ts_ms) = self.legacy.created_at_ms {
+            return Some(ts_ms / 1000);
+        }
+
+        // created_at 形如 "Thu Apr 06 15:24:15 +0000 2017"
+        let created_at = self.legacy.created_at.as_deref()?;
+        DateTime::parse_from_str(created_at, "%a %b %d %H:%M:%S %z %Y")
+            .ok()
+            .map(|dt| dt.timestamp())
+    }
+
+    /// 发布时间（UTC）
+    pub fn created_at(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp(self.timestamp()?, 0)
+    }
+
+    /// 媒体列表，优先使用 extended_entities
+    pub fn media(&self) -> &[MediaEntity] {
+        self.legacy
+            .extended_entities
+            .as_ref()
+            .or(self.legacy.entities.as_ref())
+            .map_or(&[], |e| &e.media)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TweetCore {
+    pub user_results: UserResults,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserResults {
+    #[serde(default)]
+    pub result: Option<User>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct User {
+    #[serde(default)]
+    pub rest_id: Option<String>,
+    #[serde(default)]
+    pub core: Option<UserCore>,
+    #[serde(default)]
+    pub legacy: Option<UserLegacy>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserCore {
+    #[serde(default)]
+    pub screen_name: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserLegacy {
+    #[serde(default)]
+    pub screen_name: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TweetLegacy {
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub created_at_ms: Option<i64>,
+    #[serde(default)]
+    pub full_text: Option<String>,
+    #[serde(default)]
+    pub extended_entities: Option<Entities>,
+    #[serde(default)]
+    pub entities: Option<Entities>,
+    #[serde(default)]
+    pub retweeted_status_result: Option<Box<TweetResults>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Entities {
+    #[serde(default, deserialize_with = "lenient_vec")]
+    pub media: Vec<MediaEntity>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MediaEntity {
+    /// photo、video 或 animated_gif
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub id_str: Option<String>,
+    #[serde(default)]
+    pub media_url_https: Option<String>,
+    /// 替代文本
+    #[serde(default)]
+    pub ext_alt_text: Option<String>,
+    #[serde(default)]
+    pub video_info: Option<VideoInfo>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VideoInfo {
+    #[serde(default)]
+    pub duration_millis: Option<u64>,
+    #[serde(default, deserialize_with = "lenient_vec")]
+    pub variants: Vec<VideoVariant>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VideoVariant {
+    /// m3u8 变体没有 bitrate
+    #[serde(default)]
+    pub bitrate: Option<u64>,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    pub url: String,
+}
+
+/// 逐个解析列表中的元素，格式异常的元素记录警告后跳过
+fn lenient_vec<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let values = Vec::<Value>::deserialize(deserializer)?;
+    Ok(values
+        .into_iter()
+        .filter_map(|value| {
+            let id = value
+                .get("entryId")
+                .or_else(|| value.get("id_str"))
+                .or_else(|| value.get("url"))
+                .and_then(Value::as_str)
+                .unwrap_or("未知")
+                .to_string();
+            T::deserialize(value)
+                .map_err(|e| warn!("跳过无法解析的条目 ({}): {}", id, e))
+                .ok()
+        })
+        .collect())
+}
+
+fn lenient_option_vec<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    lenient_vec(deserializer).map(Some)
+}
+
+/// created_at_ms 在不同接口中可能是字符串或数字
+fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+    match Option::<Value>::deserialize(deserializer)? {
+        Some(Value::Number(n)) => Ok(n.as_i64()),
+        Some(Value::String(s)) => Ok(s.parse().ok()),
+        _ => Ok(None),
+    }
+}
+
+impl Entry {
+    pub fn tweet_result(&self) -> Option<&TweetResult> {
+        self.content
+            .item_content
+            .as_ref()?
+            .tweet_results
+            .as_ref()?
+            .result
+            .as_ref()
+    }
+
+    /// 推文 ID；墓碑条目没有 rest_id，从 entryId（如 tweet-123）中取
+    pub fn tweet_id(&self) -> Option<&str> {
+        if let Some(TweetResult::Tweet(tweet)) = self.tweet_result() {
+            return Some(&tweet.rest_id);
+        }
+
+        let (_, id) = self.entry_id.rsplit_once("tweet-")?;
+        (!id.is_empty() && id.bytes().all(|b| b.is_ascii_digit())).then_some(id)
+    }
+
+    /// sortIndex，越新的条目越大
+    pub fn sort_index(&self) -> Option<u128> {
+        self.sort_index.as_deref()?.parse().ok()
+    }
+
+    pub fn is_tweet(&self) -> bool {
+        self.entry_id.starts_with("tweet-")
+    }
+
+    /// bottom cursor 的值，不是 bottom cursor 条目时为 None
+    pub fn bottom_cursor(&self) -> Option<&str> {
+        if !self.entry_id.starts_with("cursor-bottom-") {
+            return None;
+        }
+        self.content.value.as_deref()
+    }
+}
+
+impl ModuleItem {
+    /// 将模块条目（如 `profile-grid-0-tweet-123`）转换为与普通时间线一致的 `tweet-123` 条目
+    pub fn into_entry(self) -> Option<Entry> {
+        let (_, tweet_id) = self.entry_id.rsplit_once("-tweet-")?;
+        Some(Entry {
+            entry_id: format!("tweet-{}", tweet_id),
+            sort_index: None,
+            content: EntryContent {
+                item_content: self.item.item_content,
+                ..Default::default()
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+#[path = "models_test.rs"]
+mod tests;
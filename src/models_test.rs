@@ -0,0 +1,103 @@
+use super::*;
+use serde_json::json;
+
+fn entry(result: Value) -> Entry {
+    serde_json::from_value(json!({
+        "entryId": "tweet-5",
+        "content": { "itemContent": { "tweet_results": { "result": result } } }
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_tweet_keeps_unknown_fields() {
+    let tweet: Tweet = serde_json::from_value(json!({
+        "rest_id": "1",
+        "views": { "count": "42" },
+        "core": { "user_results": { "result": { "core": { "screen_name": "artist" } } } },
+        "legacy": { "created_at_ms": "1700000000123", "favorite_count": 3 }
+    }))
+    .unwrap();
+
+    assert_eq!(tweet.screen_name(), Some("artist"));
+    assert_eq!(tweet.timestamp(), Some(1700000000));
+    assert_eq!(tweet.extra["views"]["count"], "42");
+}
+
+#[test]
+fn test_missing_field_is_named_in_error() {
+    let err = serde_json::from_value::<Tweet>(json!({ "rest_id": "1" })).unwrap_err();
+    assert!(err.to_string().contains("missing field `legacy`"));
+
+    let err = serde_json::from_value::<VideoVariant>(json!({ "bitrate": 1 })).unwrap_err();
+    assert!(err.to_string().contains("missing field `url`"));
+}
+
+#[test]
+fn test_parse_visibility_results_and_tombstone_entries() {
+    let restricted = entry(json!({
+        "__typename": "TweetWithVisibilityResults",
+        "tweet": { "rest_id": "5", "legacy": {} }
+    }));
+    assert_eq!(restricted.tweet_id(), Some("5"));
+    assert!(matches!(restricted.tweet_result(), Some(TweetResult::Tweet(t)) if t.rest_id == "5"));
+
+    let tombstone = entry(json!({
+        "__typename": "TweetTombstone",
+        "tombstone": { "text": { "text": "This Post is from a suspended account." } }
+    }));
+    // 墓碑没有 rest_id，从 entryId 中取推文 ID
+    assert_eq!(tombstone.tweet_id(), Some("5"));
+    assert!(matches!(
+        tombstone.tweet_result(),
+        Some(TweetResult::Unavailable(reason)) if reason == "This Post is from a suspended account."
+    ));
+
+    let cursor: Entry = serde_json::from_value(
+        json!({ "entryId": "cursor-bottom-1", "content": { "value": "c1" } }),
+    )
+    .unwrap();
+    assert_eq!(cursor.tweet_id(), None);
+    assert_eq!(cursor.bottom_cursor(), Some("c1"));
+}
+
+#[test]
+fn test_malformed_entries_are_skipped() {
+    let timeline: Timeline = serde_json::from_value(json!({
+        "instructions": [{
+            "type": "TimelineAddEntries",
+            "entries": [
+                // 缺少 legacy 的推文
+                { "entryId": "tweet-1", "content": { "itemContent": { "tweet_results": { "result": { "rest_id": "1" } } } } },
+                { "entryId": "tweet-2", "content": { "itemContent": { "tweet_results": { "result": {
+                    "rest_id": "2",
+                    "legacy": { "extended_entities": { "media": [
+                        // 缺少 type 的媒体
+                        { "id_str": "20" },
+                        { "type": "video", "id_str": "21", "video_info": { "variants": [
+                            // 缺少 url 的视频变体
+                            { "bitrate": 1 },
+                            { "bitrate": 2, "url": "https://video.twimg.com/b.mp4" }
+                        ] } }
+                    ] } }
+                } } } } },
+                { "content": {} }
+            ]
+        }]
+    }))
+    .unwrap();
+
+    let Instruction::TimelineAddEntries { entries } = &timeline.instructions[0] else {
+        panic!("应为 TimelineAddEntries");
+    };
+    assert_eq!(entries.len(), 1);
+    let Some(TweetResult::Tweet(tweet)) = entries[0].tweet_result() else {
+        panic!("应为推文");
+    };
+    let media = tweet.media();
+    assert_eq!(media.len(), 1);
+    assert_eq!(media[0].id_str.as_deref(), Some("21"));
+    let variants = &media[0].video_info.as_ref().unwrap().variants;
+    assert_eq!(variants.len(), 1);
+    assert_eq!(variants[0].url, "https://video.twimg.com/b.mp4");
+}
@@ -0,0 +1,415 @@
+use anyhow::{anyhow, Context, Result};
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::embed::{child_boxes, invalid, make_box, top_level_boxes, TopBox};
+
+/// tfhd 标志：带有绝对的 base_data_offset
+const TFHD_BASE_DATA_OFFSET: u32 = 0x01;
+
+/// 分开下载的 fMP4 文件：初始化部分（ftyp、moov）和按顺序排列的分片
+struct Fmp4 {
+    file: File,
+    ftyp: Option<Vec<u8>>,
+    moov: Vec<u8>,
+    /// 各分片的 moof 和紧随其后的 mdat
+    fragments: Vec<(TopBox, TopBox)>,
+}
+
+impl Fmp4 {
+    fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path).with_context(|| format!("无法读取文件: {:?}", path))?;
+        let boxes = top_level_boxes(&mut file)?;
+
+        let mut ftyp = None;
+        let mut moov = None;
+        let mut fragments = Vec::new();
+        let mut boxes = boxes.into_iter();
+        while let Some(b) = boxes.next() {
+            match &b.kind {
+                b"ftyp" => ftyp = Some(read_box(&mut file, &b)?),
+                b"moov" => moov = Some(read_box(&mut file, &b)?),
+                // 数据偏移相对于 moof，moof 之后必须紧跟对应的 mdat
+                b"moof" => match boxes.next() {
+                    Some(mdat) if &mdat.kind == b"mdat" => fragments.push((b, mdat)),
+                    _ => return Err(anyhow!("{:?} 中的 moof 之后缺少 mdat", path)),
+                },
+                // styp、sidx 等分段信息在合并后不再有效
+                _ => {}
+            }
+        }
+
+        Ok(Fmp4 {
+            file,
+            ftyp,
+            moov: moov.ok_or_else(|| anyhow!("{:?} 缺少 moov", path))?,
+            fragments,
+        })
+    }
+}
+
+/// 将 HLS 分开提供的 fMP4 视频和音轨合并为一个分片 MP4，不需要外部工具：
+/// 音轨的 trak 和 trex 加入视频的 moov，两者的分片按解码时间交错排列
+pub fn merge_fmp4(video_path: &Path, audio_path: &Path, output: &Path) -> Result<()> {
+    let mut video = Fmp4::open(video_path)?;
+    let mut audio = Fmp4::open(audio_path)?;
+
+    let video_moov = box_payload(&video.moov)?;
+    let audio_moov = box_payload(&audio.moov)?;
+    let video_tracks = tracks(video_moov)?;
+    let (audio_trak, audio_id, audio_timescale) = tracks(audio_moov)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("音轨中没有 trak"))?;
+    // 音轨与视频的 track_ID 相同时改用新的编号
+    let max_video_id = video_tracks.iter().map(|(_, id, _)| *id).max().unwrap_or(0);
+    let new_audio_id = if video_tracks.iter().any(|(_, id, _)| *id == audio_id) {
+        max_video_id + 1
+    } else {
+        audio_id
+    };
+
+    let moov = merged_moov(video_moov, audio_moov, audio_trak, audio_id, new_audio_id)?;
+    let video_timescales: Vec<(u32, u32)> = video_tracks
+        .iter()
+        .map(|(_, id, timescale)| (*id, *timescale))
+        .collect();
+
+    let temp = output.with_extension("muxing");
+    let result = (|| -> Result<()> {
+        let mut out = BufWriter::new(File::create(&temp)?);
+        let mut written = 0u64;
+        if let Some(ftyp) = &video.ftyp {
+            out.write_all(ftyp)?;
+            written += ftyp.len() as u64;
+        }
+        out.write_all(&moov)?;
+        written += moov.len() as u64;
+
+        // 按解码时间归并两路分片，时间相同时视频在前
+        let (mut v, mut a) = (0, 0);
+        let mut sequence = 1;
+        while v < video.fragments.len() || a < audio.fragments.len() {
+            let take_video = match (video.fragments.get(v), audio.fragments.get(a)) {
+                (Some(_), None) => true,
+                (None, _) => false,
+                (Some((video_moof, _)), Some((audio_moof, _))) => {
+                    let video_time = fragment_time(&mut video.file, video_moof, &video_timescales)?;
+                    let audio_time =
+                        fragment_time(&mut audio.file, audio_moof, &[(audio_id, audio_timescale)])?;
+                    compare_time(video_time, audio_time) != Ordering::Greater
+                }
+            };
+
+            let (source, fragment, track_id) = if take_video {
+                v += 1;
+                (&mut video, v - 1, None)
+            } else {
+                a += 1;
+                (&mut audio, a - 1, Some(new_audio_id))
+            };
+            let (moof, mdat) = &source.fragments[fragment];
+            let mut moof_data = read_box(&mut source.file, moof)?;
+            patch_moof(&mut moof_data, sequence, track_id, moof.offset, written)?;
+            out.write_all(&moof_data)?;
+            source.file.seek(SeekFrom::Start(mdat.offset))?;
+            io::copy(&mut (&mut source.file).take(mdat.size), &mut out)?;
+            written += moof.size + mdat.size;
+            sequence += 1;
+        }
+        out.flush()?;
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => std::fs::rename(&temp, output).with_context(|| format!("无法重命名 {:?}", temp)),
+        Err(e) => {
+            std::fs::remove_file(&temp).ok();
+            Err(e.context("合并 HLS 视频和音轨失败"))
+        }
+    }
+}
+
+/// moov 中各 trak 的原始数据、track_ID 和媒体时间刻度
+fn tracks(moov: &[u8]) -> Result<Vec<(&[u8], u32, u32)>> {
+    child_boxes(moov)?
+        .into_iter()
+        .filter(|b| &b.kind == b"trak")
+        .map(|trak| {
+            let payload = trak.payload();
+            let tkhd = payload_range(payload, &[b"tkhd"])?.ok_or_else(|| invalid("MP4"))?;
+            let id_pos = tkhd.start + if payload[tkhd.start] == 1 { 20 } else { 12 };
+            let mdhd =
+                payload_range(payload, &[b"mdia", b"mdhd"])?.ok_or_else(|| invalid("MP4"))?;
+            let scale_pos = mdhd.start + if payload[mdhd.start] == 1 { 20 } else { 12 };
+            Ok((
+                trak.raw,
+                read_u32(payload, id_pos)?,
+                read_u32(payload, scale_pos)?,
+            ))
+        })
+        .collect()
+}
+
+/// 在视频的 moov 中加入音轨：trak 放在最后一个视频 trak 之后，trex 加入 mvex，并更新 next_track_ID
+fn merged_moov(
+    video_moov: &[u8],
+    audio_moov: &[u8],
+    audio_trak: &[u8],
+    audio_id: u32,
+    new_audio_id: u32,
+) -> Result<Vec<u8>> {
+    let video_scale = movie_timescale(video_moov)?;
+    let audio_scale = movie_timescale(audio_moov)?;
+    let audio_trak = patch_audio_trak(audio_trak, new_audio_id, audio_scale, video_scale)?;
+    let audio_trex = child_boxes(box_payload_of(audio_moov, b"mvex")?)?
+        .into_iter()
+        .filter(|b| &b.kind == b"trex")
+        .find(|b| read_u32(b.payload(), 4).ok() == Some(audio_id))
+        .map(|b| {
+            let mut trex = b.raw.to_vec();
+            write_u32(&mut trex, b.header_len + 4, new_audio_id)?;
+            Ok::<_, anyhow::Error>(trex)
+        })
+        .transpose()?
+        .ok_or_else(|| anyhow!("音轨的 mvex 中没有对应的 trex"))?;
+
+    let children = child_boxes(video_moov)?;
+    let last_trak = children
+        .iter()
+        .rposition(|b| &b.kind == b"trak")
+        .ok_or_else(|| anyhow!("视频中没有 trak"))?;
+    if !children.iter().any(|b| &b.kind == b"mvex") {
+        return Err(anyhow!("视频不是分片 MP4（缺少 mvex）"));
+    }
+
+    let mut payload = Vec::with_capacity(video_moov.len() + audio_trak.len() + audio_trex.len());
+    for (i, child) in children.iter().enumerate() {
+        match &child.kind {
+            b"mvhd" => {
+                let mut mvhd = child.raw.to_vec();
+                let pos = child.header_len + if child.payload()[0] == 1 { 108 } else { 96 };
+                let next_id = read_u32(&mvhd, pos)?.max(new_audio_id + 1);
+                write_u32(&mut mvhd, pos, next_id)?;
+                payload.extend_from_slice(&mvhd);
+            }
+            b"mvex" => {
+                let mut mvex = child.payload().to_vec();
+                mvex.extend_from_slice(&audio_trex);
+                payload.extend_from_slice(&make_box(b"mvex", &mvex));
+            }
+            _ => payload.extend_from_slice(child.raw),
+        }
+        if i == last_trak {
+            payload.extend_from_slice(&audio_trak);
+        }
+    }
+    Ok(make_box(b"moov", &payload))
+}
+
+/// mvhd 中的时间刻度，tkhd 和 elst 中的时长以它为单位
+fn movie_timescale(moov: &[u8]) -> Result<u32> {
+    let mvhd = payload_range(moov, &[b"mvhd"])?.ok_or_else(|| invalid("MP4"))?;
+    read_u32(
+        moov,
+        mvhd.start + if moov[mvhd.start] == 1 { 20 } else { 12 },
+    )
+}
+
+/// 修改音轨 trak 的 track_ID，两个文件的 mvhd 时间刻度不同时换算 tkhd 和 elst 中的时长
+fn patch_audio_trak(trak: &[u8], track_id: u32, from_scale: u32, to_scale: u32) -> Result<Vec<u8>> {
+    let mut trak = trak.to_vec();
+    let header_len = child_boxes(&trak)?
+        .first()
+        .ok_or_else(|| invalid("MP4"))?
+        .header_len;
+    let scale = |value: u64| -> u64 {
+        if from_scale == 0 || from_scale == to_scale {
+            value
+        } else {
+            (value as u128 * to_scale as u128 / from_scale as u128) as u64
+        }
+    };
+
+    let tkhd = payload_range(&trak[header_len..], &[b"tkhd"])?.ok_or_else(|| invalid("MP4"))?;
+    let tkhd_start = header_len + tkhd.start;
+    if trak[tkhd_start] == 1 {
+        write_u32(&mut trak, tkhd_start + 20, track_id)?;
+        let duration = read_u64(&trak, tkhd_start + 28)?;
+        write_u64(&mut trak, tkhd_start + 28, scale(duration))?;
+    } else {
+        write_u32(&mut trak, tkhd_start + 12, track_id)?;
+        let duration = read_u32(&trak, tkhd_start + 20)?;
+        if duration != u32::MAX {
+            write_u32(&mut trak, tkhd_start + 20, scale(duration as u64) as u32)?;
+        }
+    }
+
+    if let Some(elst) = payload_range(&trak[header_len..], &[b"edts", b"elst"])? {
+        let start = header_len + elst.start;
+        let version = trak[start];
+        let count = read_u32(&trak, start + 4)? as usize;
+        let entry_len = if version == 1 { 20 } else { 12 };
+        for i in 0..count {
+            let pos = start + 8 + i * entry_len;
+            if version == 1 {
+                let duration = read_u64(&trak, pos)?;
+                write_u64(&mut trak, pos, scale(duration))?;
+            } else {
+                let duration = read_u32(&trak, pos)?;
+                write_u32(&mut trak, pos, scale(duration as u64) as u32)?;
+            }
+        }
+    }
+    Ok(trak)
+}
+
+/// 分片的解码起始时间（tfdt）及其时间刻度，没有 tfdt 时为 0
+fn fragment_time(file: &mut File, moof: &TopBox, timescales: &[(u32, u32)]) -> Result<(u64, u32)> {
+    let data = read_box(file, moof)?;
+    for traf in child_boxes(box_payload(&data)?)?
+        .into_iter()
+        .filter(|b| &b.kind == b"traf")
+    {
+        let payload = traf.payload();
+        let Some(tfhd) = payload_range(payload, &[b"tfhd"])? else {
+            continue;
+        };
+        let track_id = read_u32(payload, tfhd.start + 4)?;
+        let Some(tfdt) = payload_range(payload, &[b"tfdt"])? else {
+            continue;
+        };
+        let time = if payload[tfdt.start] == 1 {
+            read_u64(payload, tfdt.start + 4)?
+        } else {
+            read_u32(payload, tfdt.start + 4)? as u64
+        };
+        let timescale = timescales
+            .iter()
+            .find(|(id, _)| *id == track_id)
+            .map_or(1, |(_, timescale)| *timescale);
+        return Ok((time, timescale.max(1)));
+    }
+    Ok((0, 1))
+}
+
+fn compare_time((a, a_scale): (u64, u32), (b, b_scale): (u64, u32)) -> Ordering {
+    (a as u128 * b_scale as u128).cmp(&(b as u128 * a_scale as u128))
+}
+
+/// 重新编号 mfhd，音轨的分片改用新的 track_ID。带有绝对 base_data_offset 的分片按新位置调整
+fn patch_moof(
+    moof: &mut [u8],
+    sequence: u32,
+    track_id: Option<u32>,
+    old_offset: u64,
+    new_offset: u64,
+) -> Result<()> {
+    let header_len = child_boxes(moof)?
+        .first()
+        .ok_or_else(|| invalid("MP4"))?
+        .header_len;
+    let payload = &moof[header_len..];
+    let mfhd = payload_range(payload, &[b"mfhd"])?.ok_or_else(|| invalid("MP4"))?;
+    let mut patches = vec![(header_len + mfhd.start + 4, sequence as u64, 4)];
+
+    for traf in child_boxes(payload)?
+        .into_iter()
+        .filter(|b| &b.kind == b"traf")
+    {
+        let traf_start = header_len + traf.offset + traf.header_len;
+        let Some(tfhd) = payload_range(traf.payload(), &[b"tfhd"])? else {
+            continue;
+        };
+        let tfhd_start = traf_start + tfhd.start;
+        if let Some(track_id) = track_id {
+            patches.push((tfhd_start + 4, track_id as u64, 4));
+        }
+        let flags = read_u32(moof, tfhd_start)? & 0x00FF_FFFF;
+        if flags & TFHD_BASE_DATA_OFFSET != 0 {
+            let base = read_u64(moof, tfhd_start + 8)?;
+            let moved = (base as i128 - old_offset as i128 + new_offset as i128) as u64;
+            patches.push((tfhd_start + 8, moved, 8));
+        }
+    }
+
+    for (pos, value, len) in patches {
+        if len == 4 {
+            write_u32(moof, pos, value as u32)?;
+        } else {
+            write_u64(moof, pos, value)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_box(file: &mut File, b: &TopBox) -> Result<Vec<u8>> {
+    let mut data = vec![0u8; b.size as usize];
+    file.seek(SeekFrom::Start(b.offset))?;
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// 单个 box 的内容（去掉头部）
+fn box_payload(data: &[u8]) -> Result<&[u8]> {
+    let header_len = child_boxes(data)?
+        .first()
+        .ok_or_else(|| invalid("MP4"))?
+        .header_len;
+    Ok(&data[header_len..])
+}
+
+/// `data` 中某个子 box 的内容
+fn box_payload_of<'a>(data: &'a [u8], kind: &[u8; 4]) -> Result<&'a [u8]> {
+    let range = payload_range(data, &[kind])?.ok_or_else(|| invalid("MP4"))?;
+    Ok(&data[range])
+}
+
+/// 按路径逐层查找第一个匹配的子 box，返回其内容在 `data` 中的范围
+fn payload_range(data: &[u8], path: &[&[u8; 4]]) -> Result<Option<Range<usize>>> {
+    let mut range = 0..data.len();
+    for kind in path {
+        let Some(b) = child_boxes(&data[range.clone()])?
+            .into_iter()
+            .find(|b| &b.kind == *kind)
+        else {
+            return Ok(None);
+        };
+        let start = range.start + b.offset;
+        range = start + b.header_len..start + b.raw.len();
+    }
+    Ok(Some(range))
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Result<u32> {
+    data.get(pos..pos + 4)
+        .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid("MP4"))
+}
+
+fn read_u64(data: &[u8], pos: usize) -> Result<u64> {
+    data.get(pos..pos + 8)
+        .map(|b| u64::from_be_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid("MP4"))
+}
+
+fn write_u32(data: &mut [u8], pos: usize, value: u32) -> Result<()> {
+    data.get_mut(pos..pos + 4)
+        .ok_or_else(|| invalid("MP4"))?
+        .copy_from_slice(&value.to_be_bytes());
+    Ok(())
+}
+
+fn write_u64(data: &mut [u8], pos: usize, value: u64) -> Result<()> {
+    data.get_mut(pos..pos + 8)
+        .ok_or_else(|| invalid("MP4"))?
+        .copy_from_slice(&value.to_be_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+#[path = "mux_test.rs"]
+mod tests;
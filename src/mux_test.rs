@@ -0,0 +1,206 @@
+use super::*;
+use std::fs;
+use std::path::PathBuf;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "x_likes_downloader_{}_mux_{}",
+        std::process::id(),
+        name
+    ))
+}
+
+fn full_box(kind: &[u8; 4], version: u8, flags: u32, body: &[u8]) -> Vec<u8> {
+    let mut payload = ((version as u32) << 24 | flags).to_be_bytes().to_vec();
+    payload.extend_from_slice(body);
+    make_box(kind, &payload)
+}
+
+fn concat(parts: &[Vec<u8>]) -> Vec<u8> {
+    parts.concat()
+}
+
+/// 只有一个 trak 的分片 MP4 初始化部分
+fn init(track_id: u32, timescale: u32, with_mvex: bool) -> Vec<u8> {
+    let mut mvhd = vec![0; 96];
+    mvhd[8..12].copy_from_slice(&1000u32.to_be_bytes());
+    mvhd[92..96].copy_from_slice(&(track_id + 1).to_be_bytes());
+
+    let mut tkhd = vec![0; 80];
+    tkhd[8..12].copy_from_slice(&track_id.to_be_bytes());
+    tkhd[16..20].copy_from_slice(&5000u32.to_be_bytes());
+    let mut mdhd = vec![0; 20];
+    mdhd[8..12].copy_from_slice(&timescale.to_be_bytes());
+    let trak = make_box(
+        b"trak",
+        &concat(&[
+            full_box(b"tkhd", 0, 3, &tkhd),
+            make_box(b"mdia", &full_box(b"mdhd", 0, 0, &mdhd)),
+        ]),
+    );
+
+    let mut moov = vec![full_box(b"mvhd", 0, 0, &mvhd), trak];
+    if with_mvex {
+        let mut trex = track_id.to_be_bytes().to_vec();
+        trex.extend_from_slice(&[0; 16]);
+        moov.push(make_box(b"mvex", &full_box(b"trex", 0, 0, &trex)));
+    }
+    concat(&[
+        make_box(b"ftyp", b"iso6\0\0\0\0"),
+        make_box(b"moov", &concat(&moov)),
+    ])
+}
+
+/// 一个分片：moof（mfhd、traf）和 mdat。base_data_offset 为 Some 时写入 tfhd
+fn fragment(track_id: u32, time: u64, base_data_offset: Option<u64>, data: &[u8]) -> Vec<u8> {
+    let mut tfhd = track_id.to_be_bytes().to_vec();
+    let flags = match base_data_offset {
+        Some(offset) => {
+            tfhd.extend_from_slice(&offset.to_be_bytes());
+            TFHD_BASE_DATA_OFFSET
+        }
+        None => 0x020000,
+    };
+    let traf = make_box(
+        b"traf",
+        &concat(&[
+            full_box(b"tfhd", 0, flags, &tfhd),
+            full_box(b"tfdt", 1, 0, &time.to_be_bytes()),
+        ]),
+    );
+    concat(&[
+        make_box(
+            b"moof",
+            &concat(&[full_box(b"mfhd", 0, 0, &99u32.to_be_bytes()), traf]),
+        ),
+        make_box(b"mdat", data),
+    ])
+}
+
+/// 输出文件中各分片的 mfhd 序号、tfhd track_ID、tfhd 标志和位置，以及 mdat 内容
+struct Fragment {
+    sequence: u32,
+    track_id: u32,
+    base_data_offset: Option<u64>,
+    moof_offset: u64,
+    data: Vec<u8>,
+}
+
+fn fragments(data: &[u8]) -> Vec<Fragment> {
+    let boxes = child_boxes(data).unwrap();
+    let mut result = Vec::new();
+    for (i, b) in boxes.iter().enumerate() {
+        if &b.kind != b"moof" {
+            continue;
+        }
+        let payload = b.payload();
+        let mfhd = payload_range(payload, &[b"mfhd"]).unwrap().unwrap();
+        let tfhd = payload_range(payload, &[b"traf", b"tfhd"])
+            .unwrap()
+            .unwrap();
+        let flags = read_u32(payload, tfhd.start).unwrap() & 0x00FF_FFFF;
+        result.push(Fragment {
+            sequence: read_u32(payload, mfhd.start + 4).unwrap(),
+            track_id: read_u32(payload, tfhd.start + 4).unwrap(),
+            base_data_offset: (flags & TFHD_BASE_DATA_OFFSET != 0)
+                .then(|| read_u64(payload, tfhd.start + 8).unwrap()),
+            moof_offset: b.offset as u64,
+            data: boxes[i + 1].payload().to_vec(),
+        });
+    }
+    result
+}
+
+fn merge(video: &[u8], audio: &[u8], name: &str) -> Result<Vec<u8>> {
+    let video_path = temp_path(&format!("{}_video.mp4", name));
+    let audio_path = temp_path(&format!("{}_audio.m4a", name));
+    let output = temp_path(&format!("{}.mp4", name));
+    fs::write(&video_path, video).unwrap();
+    fs::write(&audio_path, audio).unwrap();
+
+    let result = merge_fmp4(&video_path, &audio_path, &output).map(|_| fs::read(&output).unwrap());
+    fs::remove_file(&video_path).ok();
+    fs::remove_file(&audio_path).ok();
+    fs::remove_file(&output).ok();
+    result
+}
+
+#[test]
+fn test_merge_fmp4_interleaves_fragments_by_time() {
+    // 视频 90kHz，每个分片 2 秒；音频 48kHz，每个分片 1 秒，两者的 track_ID 都是 1
+    let video = concat(&[
+        init(1, 90000, true),
+        make_box(b"styp", b"msdh\0\0\0\0"),
+        fragment(1, 0, None, b"v0"),
+        fragment(1, 180000, None, b"v1"),
+    ]);
+    let audio = concat(&[
+        init(1, 48000, true),
+        fragment(1, 0, None, b"a0"),
+        fragment(1, 48000, None, b"a1"),
+        fragment(1, 96000, None, b"a2"),
+    ]);
+
+    let data = merge(&video, &audio, "interleave").unwrap();
+    let fragments = fragments(&data);
+    let order: Vec<&[u8]> = fragments.iter().map(|f| f.data.as_slice()).collect();
+    assert_eq!(order, [&b"v0"[..], b"a0", b"a1", b"v1", b"a2"]);
+    let sequences: Vec<u32> = fragments.iter().map(|f| f.sequence).collect();
+    assert_eq!(sequences, [1, 2, 3, 4, 5]);
+    let track_ids: Vec<u32> = fragments.iter().map(|f| f.track_id).collect();
+    assert_eq!(track_ids, [1, 2, 2, 1, 2]);
+    assert!(!data.windows(4).any(|w| w == b"styp"));
+
+    // 音轨改用新的 track_ID，trex 和 next_track_ID 随之更新
+    let moov = child_boxes(&data)
+        .unwrap()
+        .into_iter()
+        .find(|b| &b.kind == b"moov")
+        .unwrap();
+    let tracks = tracks(moov.payload()).unwrap();
+    let ids: Vec<(u32, u32)> = tracks.iter().map(|(_, id, scale)| (*id, *scale)).collect();
+    assert_eq!(ids, [(1, 90000), (2, 48000)]);
+    let mvex = box_payload_of(moov.payload(), b"mvex").unwrap();
+    let trex_ids: Vec<u32> = child_boxes(mvex)
+        .unwrap()
+        .iter()
+        .map(|b| read_u32(b.payload(), 4).unwrap())
+        .collect();
+    assert_eq!(trex_ids, [1, 2]);
+    let mvhd = payload_range(moov.payload(), &[b"mvhd"]).unwrap().unwrap();
+    assert_eq!(read_u32(moov.payload(), mvhd.start + 96).unwrap(), 3);
+}
+
+#[test]
+fn test_merge_fmp4_moves_base_data_offset() {
+    let video_init = init(1, 1000, true);
+    let moof_offset = video_init.len() as u64;
+    let video = concat(&[video_init, fragment(1, 0, Some(moof_offset), b"video")]);
+    let audio_init = init(2, 1000, true);
+    let audio_offset = audio_init.len() as u64;
+    let audio = concat(&[audio_init, fragment(2, 0, Some(audio_offset + 5), b"audio")]);
+
+    let data = merge(&video, &audio, "offset").unwrap();
+    let fragments = fragments(&data);
+    assert_eq!(fragments.len(), 2);
+    // 不冲突的 track_ID 保持不变，绝对偏移相对 moof 的位置不变
+    assert_eq!(fragments[1].track_id, 2);
+    assert_eq!(
+        fragments[0].base_data_offset,
+        Some(fragments[0].moof_offset)
+    );
+    assert_eq!(
+        fragments[1].base_data_offset,
+        Some(fragments[1].moof_offset + 5)
+    );
+}
+
+#[test]
+fn test_merge_fmp4_requires_fragmented_video() {
+    let video = init(1, 1000, false);
+    let audio = concat(&[init(1, 1000, true), fragment(1, 0, None, b"a0")]);
+
+    let err = merge(&video, &audio, "plain").unwrap_err();
+    assert!(format!("{:#}", err).contains("mvex"));
+    assert!(!temp_path("plain.muxing").exists());
+}
@@ -2,6 +2,7 @@ use anyhow::Result;
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::Path;
+use tracing::{error, info, warn};
 
 pub struct FileOrganizer;
 
@@ -35,7 +36,7 @@ impl FileOrganizer {
         let alias_file = a_path.join("username_aliases.txt");
         let aliases = Self::load_username_aliases(&alias_file.to_string_lossy());
         if !aliases.is_empty() {
-            println!("已加载 {} 条用户名别名映射", aliases.len());
+            info!("已加载 {} 条用户名别名映射", aliases.len());
         }
 
         // 统计变量
@@ -53,8 +54,8 @@ impl FileOrganizer {
                 let file_name = entry.file_name();
                 let filename = file_name.to_string_lossy();
 
-                // 跳过配置文件
-                if filename == "username_aliases.txt" {
+                // 跳过配置文件和未下载完成的 .part 文件
+                if filename == "username_aliases.txt" || filename.ends_with(".part") {
                     continue;
                 }
 
@@ -98,7 +99,7 @@ impl FileOrganizer {
                                         deleted_count += 1;
                                     }
                                     Err(e) => {
-                                        println!("  删除同名文件失败: {}", e);
+                                        error!("删除同名文件失败: {}", e);
                                         error_count += 1;
                                         continue;
                                     }
@@ -108,14 +109,14 @@ impl FileOrganizer {
                             // 移动文件到目标目录
                             match fs::rename(&file_path, &destination) {
                                 Ok(_) => {
-                                    println!(
+                                    info!(
                                         "已将 {} 移动到 {:?} - 用户: {}, ID: {}",
                                         filename, target_folder_path, username, tweet_id
                                     );
                                     moved_count += 1;
                                 }
                                 Err(e) => {
-                                    println!(
+                                    error!(
                                         "移动文件失败: {} -> {:?}, 错误: {}",
                                         filename, destination, e
                                     );
@@ -124,12 +125,12 @@ impl FileOrganizer {
                             }
                         } else {
                             if match_username != username {
-                                println!(
+                                warn!(
                                     "未找到用户名为 {}（别名 {} 的主名称）的目标文件夹，跳过 {}",
                                     match_username, username, filename
                                 );
                             } else {
-                                println!(
+                                warn!(
                                     "未找到用户名为 {} 的目标文件夹，跳过 {}",
                                     username, filename
                                 );
@@ -138,7 +139,7 @@ impl FileOrganizer {
                         }
                     }
                     Err(error_msg) => {
-                        println!("文件 {} 解析失败: {}", filename, error_msg);
+                        warn!("文件 {} 解析失败: {}", filename, error_msg);
                         skipped_count += 1;
                     }
                 }
@@ -146,11 +147,11 @@ impl FileOrganizer {
         }
 
         // 打印处理统计
-        println!("\n=== 文件整理统计 ===");
-        println!("成功移动文件: {}", moved_count);
-        println!("跳过文件: {}", skipped_count);
-        println!("删除同名文件: {}", deleted_count);
-        println!("处理错误: {}", error_count);
+        info!("\n=== 文件整理统计 ===");
+        info!("成功移动文件: {}", moved_count);
+        info!("跳过文件: {}", skipped_count);
+        info!("删除同名文件: {}", deleted_count);
+        info!("处理错误: {}", error_count);
 
         Ok(())
     }
@@ -161,8 +162,8 @@ impl FileOrganizer {
 
         while let Some(next) = aliases.get(&current) {
             if visited.contains(&current) {
-                eprintln!(
-                    "警告: 检测到别名循环，涉及用户名 '{}'. 使用原始用户名。",
+                warn!(
+                    "检测到别名循环，涉及用户名 '{}'. 使用原始用户名。",
                     username
                 );
                 return username.to_string();
@@ -183,7 +184,7 @@ impl FileOrganizer {
         let content = match fs::read_to_string(path) {
             Ok(c) => c,
             Err(e) => {
-                eprintln!("警告: 无法读取别名文件 {}: {}", alias_file_path, e);
+                warn!("无法读取别名文件 {}: {}", alias_file_path, e);
                 return HashMap::new();
             }
         };
@@ -259,16 +260,16 @@ impl FileOrganizer {
         let source_size = fs::metadata(source_path)?.len();
         let target_size = fs::metadata(target_path)?.len();
 
-        println!(
+        info!(
             "发现同名文件: {}",
             source_path.file_name().unwrap().to_string_lossy()
         );
-        println!("  源文件大小: {} 字节", source_size);
-        println!("  目标文件大小: {} 字节", target_size);
+        info!("  源文件大小: {} 字节", source_size);
+        info!("  目标文件大小: {} 字节", target_size);
 
         // 删除目标目录中的同名文件
         fs::remove_file(target_path)?;
-        println!("  已删除目标目录中的同名文件: {:?}", target_path);
+        info!("  已删除目标目录中的同名文件: {:?}", target_path);
 
         Ok(())
     }
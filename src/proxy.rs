@@ -0,0 +1,107 @@
+use anyhow::{anyhow, Context, Result};
+use reqwest::{ClientBuilder, Proxy};
+use serde::{Deserialize, Serialize};
+use std::env;
+use url::Url;
+
+/// 代理设置，API、媒体下载和更新检查的客户端共用
+///
+/// 更新检查不需要令牌文件，因此可以不经过 Config 直接从环境变量读取。
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ProxySettings {
+    /// 所有请求使用的代理，如 http://127.0.0.1:7890
+    pub proxy: String,
+    /// 只用于 HTTPS 请求的代理，优先于 PROXY
+    pub https_proxy: String,
+    /// SOCKS5 代理，如 127.0.0.1:1080，域名在代理端解析
+    pub socks5: String,
+    pub proxy_username: String,
+    pub proxy_password: String,
+    /// 忽略所有代理设置（包括系统代理），直接连接
+    pub no_proxy: bool,
+}
+
+impl ProxySettings {
+    pub fn from_env() -> Self {
+        ProxySettings {
+            proxy: env::var("PROXY").unwrap_or_default(),
+            https_proxy: env::var("HTTPS_PROXY").unwrap_or_default(),
+            socks5: env::var("SOCKS5").unwrap_or_default(),
+            proxy_username: env::var("PROXY_USERNAME").unwrap_or_default(),
+            proxy_password: env::var("PROXY_PASSWORD").unwrap_or_default(),
+            no_proxy: false,
+        }
+    }
+
+    /// 为客户端配置代理。没有设置任何代理时保持 reqwest 默认行为（使用系统代理）
+    pub fn apply(&self, builder: ClientBuilder) -> Result<ClientBuilder> {
+        if self.no_proxy {
+            return Ok(builder.no_proxy());
+        }
+
+        let mut builder = builder;
+        for (scheme, url) in self.proxy_urls()? {
+            let proxy = match scheme {
+                ProxyScheme::Https => Proxy::https(&url),
+                ProxyScheme::All => Proxy::all(&url),
+            };
+            builder = builder.proxy(proxy.with_context(|| format!("无效的代理地址: {}", url))?);
+        }
+        Ok(builder)
+    }
+
+    /// 按优先级排列的代理地址，reqwest 使用第一个匹配请求协议的代理
+    fn proxy_urls(&self) -> Result<Vec<(ProxyScheme, String)>> {
+        let proxy = self.proxy.trim();
+        let socks5 = self.socks5.trim();
+        if !proxy.is_empty() && !socks5.is_empty() {
+            return Err(anyhow!("PROXY 和 SOCKS5 只能设置一个"));
+        }
+
+        let mut urls = Vec::new();
+        if !self.https_proxy.trim().is_empty() {
+            urls.push((
+                ProxyScheme::Https,
+                self.proxy_url("HTTPS_PROXY", &self.https_proxy, "http")?,
+            ));
+        }
+        if !proxy.is_empty() {
+            urls.push((ProxyScheme::All, self.proxy_url("PROXY", proxy, "http")?));
+        }
+        if !socks5.is_empty() {
+            urls.push((
+                ProxyScheme::All,
+                self.proxy_url("SOCKS5", socks5, "socks5h")?,
+            ));
+        }
+        Ok(urls)
+    }
+
+    /// 补全协议，并把 PROXY_USERNAME/PROXY_PASSWORD 写入地址（地址中已有的认证信息优先）
+    fn proxy_url(&self, name: &str, raw: &str, default_scheme: &str) -> Result<String> {
+        let raw = raw.trim();
+        let raw = if raw.contains("://") {
+            raw.to_string()
+        } else {
+            format!("{}://{}", default_scheme, raw)
+        };
+        let mut url = Url::parse(&raw).with_context(|| format!("{} 不是有效的代理地址", name))?;
+
+        if url.username().is_empty() && !self.proxy_username.is_empty() {
+            url.set_username(&self.proxy_username)
+                .and_then(|_| url.set_password(Some(&self.proxy_password)))
+                .map_err(|_| anyhow!("{} 不支持设置用户名和密码", name))?;
+        }
+        Ok(url.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProxyScheme {
+    Https,
+    All,
+}
+
+#[cfg(test)]
+#[path = "proxy_test.rs"]
+mod tests;
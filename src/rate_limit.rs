@@ -0,0 +1,215 @@
+use chrono::{Local, TimeZone};
+use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use crate::logging;
+
+/// 指数退避的基础等待时间（秒）
+const BACKOFF_BASE_SECS: u64 = 2;
+/// 指数退避的最长等待时间（秒）
+const BACKOFF_MAX_SECS: u64 = 120;
+/// 剩余额度不超过该值时开始拉开请求间隔
+const PACING_THRESHOLD: u64 = 10;
+
+/// 单个 GraphQL 接口的请求额度
+#[derive(Debug, Clone, Copy)]
+struct Budget {
+    remaining: u64,
+    /// 额度重置时间（Unix 秒）
+    reset: i64,
+}
+
+/// 根据 `x-rate-limit-*` 响应头跟踪各接口的剩余额度，并决定重试前的等待时间
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    budgets: Mutex<HashMap<String, Budget>>,
+}
+
+impl RateLimiter {
+    /// 记录响应头中的剩余额度和重置时间
+    pub fn update(&self, endpoint: &str, headers: &HeaderMap) {
+        let header_value = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<i64>().ok())
+        };
+
+        if let (Some(remaining), Some(reset)) = (
+            header_value("x-rate-limit-remaining"),
+            header_value("x-rate-limit-reset"),
+        ) {
+            self.budgets.lock().unwrap().insert(
+                endpoint.to_string(),
+                Budget {
+                    remaining: remaining.max(0) as u64,
+                    reset,
+                },
+            );
+        }
+    }
+
+    /// 额度已用完时等待到重置时间；额度所剩不多时把剩余请求平均分配到重置前的时间里
+    pub async fn wait_for_budget(&self, endpoint: &str) {
+        let budget = self.budgets.lock().unwrap().get(endpoint).copied();
+        match budget {
+            Some(Budget {
+                remaining: 0,
+                reset,
+            }) => {
+                if let Some(delay) = until_reset(reset) {
+                    countdown(
+                        delay,
+                        &format!("请求额度已用完，{} 重置", format_reset(reset)),
+                    )
+                    .await;
+                }
+                self.budgets.lock().unwrap().remove(endpoint);
+            }
+            Some(Budget { remaining, reset }) => {
+                if let Some(delay) = pacing_delay(remaining, reset) {
+                    countdown(
+                        delay,
+                        &format!(
+                            "请求额度仅剩 {} 次，{} 重置，放慢请求",
+                            remaining,
+                            format_reset(reset)
+                        ),
+                    )
+                    .await;
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// 计算第 `attempt` 次重试前的等待时间：429 优先等到额度重置，否则按指数退避
+    pub fn retry_delay(
+        &self,
+        endpoint: &str,
+        status: Option<StatusCode>,
+        attempt: u32,
+    ) -> Duration {
+        if status == Some(StatusCode::TOO_MANY_REQUESTS) {
+            let reset = self
+                .budgets
+                .lock()
+                .unwrap()
+                .get(endpoint)
+                .and_then(|b| until_reset(b.reset));
+            if let Some(delay) = reset {
+                return delay;
+            }
+        }
+        backoff_delay(attempt)
+    }
+}
+
+/// 是否为可重试的响应状态（429 或 5xx）
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// 指数退避并加入最多 1 秒的随机抖动，避免多个请求同时重试
+pub fn backoff_delay(attempt: u32) -> Duration {
+    static JITTER: OnceLock<Jitter> = OnceLock::new();
+    backoff_delay_with(attempt, JITTER.get_or_init(Jitter::from_time))
+}
+
+fn backoff_delay_with(attempt: u32, jitter: &Jitter) -> Duration {
+    let exp = BACKOFF_BASE_SECS
+        .saturating_mul(1u64 << attempt.saturating_sub(1).min(16))
+        .min(BACKOFF_MAX_SECS);
+    Duration::from_secs(exp) + Duration::from_millis(jitter.below(1000))
+}
+
+/// 用于退避抖动的伪随机数发生器（SplitMix64），可以多线程共用，测试时可指定种子
+#[derive(Debug)]
+pub struct Jitter {
+    state: AtomicU64,
+}
+
+impl Jitter {
+    pub fn new(seed: u64) -> Self {
+        Jitter {
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    /// 以当前时间为种子
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Self::new(nanos)
+    }
+
+    /// `[0, max)` 之间的随机数
+    pub fn below(&self, max: u64) -> u64 {
+        const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut z = self
+            .state
+            .fetch_add(GAMMA, Ordering::Relaxed)
+            .wrapping_add(GAMMA);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        (z ^ (z >> 31)) % max.max(1)
+    }
+}
+
+/// 剩余额度不超过 PACING_THRESHOLD 时，下一次请求前的等待时间
+fn pacing_delay(remaining: u64, reset: i64) -> Option<Duration> {
+    if remaining == 0 || remaining > PACING_THRESHOLD {
+        return None;
+    }
+    until_reset(reset).map(|delay| delay / (remaining as u32 + 1))
+}
+
+/// 距离重置时间的等待时长，多等 1 秒以免服务端时钟偏差
+fn until_reset(reset: i64) -> Option<Duration> {
+    let secs = reset - Local::now().timestamp() + 1;
+    (secs > 0).then(|| Duration::from_secs(secs as u64))
+}
+
+fn format_reset(reset: i64) -> String {
+    Local
+        .timestamp_opt(reset, 0)
+        .single()
+        .map(|t| t.format("%H:%M:%S").to_string())
+        .unwrap_or_else(|| reset.to_string())
+}
+
+/// 带倒计时显示的等待
+pub async fn countdown(delay: Duration, reason: &str) {
+    warn!("{}，等待 {} 秒", reason, delay.as_secs());
+
+    let pb = logging::progress().add(ProgressBar::new_spinner());
+    pb.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.yellow} {msg}")
+            .unwrap(),
+    );
+
+    let deadline = tokio::time::Instant::now() + delay;
+    loop {
+        let left = deadline.saturating_duration_since(tokio::time::Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        pb.set_message(format!("{}，等待 {} 秒后继续", reason, left.as_secs() + 1));
+        pb.tick();
+        tokio::time::sleep(left.min(Duration::from_secs(1))).await;
+    }
+
+    pb.finish_and_clear();
+}
+
+#[cfg(test)]
+#[path = "rate_limit_test.rs"]
+mod tests;
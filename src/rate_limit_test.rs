@@ -0,0 +1,86 @@
+use super::*;
+
+fn rate_limit_headers(remaining: i64, reset: i64) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("x-rate-limit-remaining", remaining.into());
+    headers.insert("x-rate-limit-reset", reset.into());
+    headers
+}
+
+#[test]
+fn test_backoff_delay_grows_and_is_capped() {
+    let first = backoff_delay(1);
+    assert!(first >= Duration::from_secs(2) && first < Duration::from_secs(3));
+    let third = backoff_delay(3);
+    assert!(third >= Duration::from_secs(8) && third < Duration::from_secs(9));
+    let capped = backoff_delay(30);
+    assert!(capped >= Duration::from_secs(BACKOFF_MAX_SECS));
+    assert!(capped < Duration::from_secs(BACKOFF_MAX_SECS + 1));
+}
+
+#[test]
+fn test_jitter_is_deterministic_for_seed() {
+    let (a, b) = (Jitter::new(42), Jitter::new(42));
+    let first: Vec<u64> = (0..8).map(|_| a.below(1000)).collect();
+    let second: Vec<u64> = (0..8).map(|_| b.below(1000)).collect();
+    assert_eq!(first, second);
+    assert!(first.iter().all(|ms| *ms < 1000));
+    // 连续的值不应相同，否则多个请求会同时重试
+    assert!(first.windows(2).any(|w| w[0] != w[1]));
+
+    let delay = backoff_delay_with(2, &Jitter::new(42));
+    assert_eq!(
+        delay,
+        Duration::from_secs(4) + Duration::from_millis(first[0])
+    );
+}
+
+#[test]
+fn test_pacing_delay_spreads_remaining_requests() {
+    let reset = Local::now().timestamp() + 59;
+    // 剩余 5 次，重置前约 60 秒，每次请求间隔约 10 秒
+    let delay = pacing_delay(5, reset).unwrap();
+    assert!(delay >= Duration::from_secs(9) && delay <= Duration::from_secs(10));
+
+    assert!(pacing_delay(PACING_THRESHOLD + 1, reset).is_none());
+    assert!(pacing_delay(0, reset).is_none());
+    assert!(pacing_delay(5, Local::now().timestamp() - 10).is_none());
+}
+
+#[test]
+fn test_retryable_status() {
+    assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+    assert!(is_retryable_status(StatusCode::BAD_GATEWAY));
+    assert!(!is_retryable_status(StatusCode::UNAUTHORIZED));
+    assert!(!is_retryable_status(StatusCode::NOT_FOUND));
+}
+
+#[test]
+fn test_retry_delay_waits_for_reset_on_429() {
+    let limiter = RateLimiter::default();
+    let reset = Local::now().timestamp() + 30;
+    limiter.update("/Likes", &rate_limit_headers(0, reset));
+
+    let delay = limiter.retry_delay("/Likes", Some(StatusCode::TOO_MANY_REQUESTS), 1);
+    assert!(delay >= Duration::from_secs(29) && delay <= Duration::from_secs(31));
+
+    // 其它接口或 5xx 仍按指数退避
+    let other = limiter.retry_delay("/Bookmarks", Some(StatusCode::TOO_MANY_REQUESTS), 1);
+    assert!(other < Duration::from_secs(3));
+    let server_error = limiter.retry_delay("/Likes", Some(StatusCode::BAD_GATEWAY), 1);
+    assert!(server_error < Duration::from_secs(3));
+}
+
+#[tokio::test]
+async fn test_wait_for_budget_skips_when_remaining() {
+    let limiter = RateLimiter::default();
+    limiter.update(
+        "/Likes",
+        &rate_limit_headers(50, Local::now().timestamp() + 600),
+    );
+
+    // 额度充足时不等待
+    let started = std::time::Instant::now();
+    limiter.wait_for_budget("/Likes").await;
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
@@ -0,0 +1,61 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::models::Tweet;
+
+/// 媒体文件旁的元数据文件 `<文件名>.json`，供照片管理等外部工具建立索引
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Sidecar {
+    pub tweet_id: String,
+    pub tweet_url: String,
+    pub author_id: Option<String>,
+    pub screen_name: Option<String>,
+    pub display_name: Option<String>,
+    pub text: Option<String>,
+    /// 发布时间（RFC 3339，UTC）
+    pub created_at: Option<String>,
+    pub alt_text: Option<String>,
+    /// 媒体的下载地址
+    pub media_url: String,
+}
+
+impl Sidecar {
+    /// `tweet_id` 为媒体所属推文的 ID，引用和转推时为原推文
+    pub fn new(tweet: &Tweet, tweet_id: &str, media_url: &str, alt_text: Option<&str>) -> Self {
+        let screen_name = tweet.screen_name();
+        Sidecar {
+            tweet_id: tweet_id.to_string(),
+            tweet_url: format!(
+                "https://x.com/{}/status/{}",
+                screen_name.unwrap_or("i"),
+                tweet_id
+            ),
+            author_id: tweet.author_id().map(str::to_string),
+            screen_name: screen_name.map(str::to_string),
+            display_name: tweet.display_name().map(str::to_string),
+            text: tweet.text().map(str::to_string),
+            created_at: tweet.created_at().map(|dt| dt.to_rfc3339()),
+            alt_text: alt_text.map(str::to_string),
+            media_url: media_url.to_string(),
+        }
+    }
+
+    /// 媒体文件对应的元数据文件路径，如 `a.jpg` -> `a.jpg.json`
+    pub fn path_for(media_path: &Path) -> PathBuf {
+        let mut path = media_path.as_os_str().to_owned();
+        path.push(".json");
+        PathBuf::from(path)
+    }
+
+    pub fn write(&self, media_path: &Path) -> Result<()> {
+        let path = Self::path_for(media_path);
+        fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("无法写入元数据文件: {:?}", path))
+    }
+}
+
+#[cfg(test)]
+#[path = "sidecar_test.rs"]
+mod tests;
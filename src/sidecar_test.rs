@@ -0,0 +1,44 @@
+use super::*;
+use serde_json::json;
+
+#[test]
+fn test_sidecar_from_tweet() {
+    let tweet: Tweet = serde_json::from_value(json!({
+        "rest_id": "100",
+        "core": { "user_results": { "result": {
+            "rest_id": "42",
+            "core": { "screen_name": "alice", "name": "Alice 🌸" }
+        } } },
+        "legacy": {
+            "created_at": "Thu Apr 06 15:24:15 +0000 2017",
+            "full_text": "看这张图 https://t.co/x"
+        }
+    }))
+    .unwrap();
+
+    let sidecar = Sidecar::new(
+        &tweet,
+        "100",
+        "https://pbs.twimg.com/media/a.jpg",
+        Some("一只猫"),
+    );
+    assert_eq!(
+        serde_json::to_value(&sidecar).unwrap(),
+        json!({
+            "tweet_id": "100",
+            "tweet_url": "https://x.com/alice/status/100",
+            "author_id": "42",
+            "screen_name": "alice",
+            "display_name": "Alice 🌸",
+            "text": "看这张图 https://t.co/x",
+            "created_at": "2017-04-06T15:24:15+00:00",
+            "alt_text": "一只猫",
+            "media_url": "https://pbs.twimg.com/media/a.jpg"
+        })
+    );
+
+    assert_eq!(
+        Sidecar::path_for(Path::new("data/downloads/alice_100_a.jpg")),
+        PathBuf::from("data/downloads/alice_100_a.jpg.json")
+    );
+}
@@ -5,6 +5,8 @@ use std::env;
 use std::fs;
 use std::process::Command;
 
+use crate::proxy::ProxySettings;
+
 const GITHUB_API_BASE: &str = "https://api.github.com";
 const REPO_OWNER: &str = "HerbertGao";
 const REPO_NAME: &str = "x_likes_downloader";
@@ -14,9 +16,9 @@ pub struct Updater {
 }
 
 impl Updater {
-    pub fn new() -> Result<Self> {
-        let client = Client::builder()
-            .user_agent("x_likes_downloader_updater/1.0")
+    pub fn new(proxy: &ProxySettings) -> Result<Self> {
+        let client = proxy
+            .apply(Client::builder().user_agent("x_likes_downloader_updater/1.0"))?
             .build()?;
         Ok(Self { client })
     }
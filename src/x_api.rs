@@ -1,143 +1,693 @@
-use anyhow::Result;
-use reqwest::Client;
+use anyhow::{Context, Result};
+use futures_util::stream::{self, Stream};
+use reqwest::header::{HeaderMap, HeaderName, AUTHORIZATION, COOKIE};
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
 use serde_json::{json, Value};
+use std::fs;
+use tracing::{debug, info};
+use url::Url;
 
-use crate::config::Config;
+use crate::config::{Config, REDACTED};
+use crate::models::{
+    BookmarksResponse, Entry, Instruction, ModuleItem, Tweet, TweetDetailResponse, TweetResult,
+    UserByScreenNameResponse, UserTimelineResponse,
+};
+use crate::rate_limit::{self, RateLimiter};
+
+/// 可下载的时间线
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Timeline {
+    /// 点赞
+    Likes,
+    /// 书签
+    Bookmarks,
+    /// 指定用户的媒体时间线
+    UserMedia {
+        user_id: String,
+        screen_name: String,
+    },
+}
+
+impl Timeline {
+    /// 用于输出的中文名称
+    pub fn label(&self) -> &'static str {
+        match self {
+            Timeline::Likes => "点赞",
+            Timeline::Bookmarks => "书签",
+            Timeline::UserMedia { .. } => "用户媒体",
+        }
+    }
+
+    /// 数据库中的下载来源，不同来源分开记录
+    pub fn catalog_list(&self) -> &'static str {
+        match self {
+            Timeline::Likes => "likes",
+            Timeline::Bookmarks => "bookmarks",
+            Timeline::UserMedia { .. } => "user_media",
+        }
+    }
+
+    /// 该时间线的旧版下载记录文件，第一次运行时导入数据库
+    pub fn download_record<'a>(&self, config: &'a Config) -> &'a str {
+        match self {
+            Timeline::Likes => &config.download_record,
+            Timeline::Bookmarks => &config.bookmarks_download_record,
+            Timeline::UserMedia { .. } => &config.user_media_download_record,
+        }
+    }
+
+    /// 在抓取状态文件中使用的键
+    pub fn state_key(&self) -> String {
+        match self {
+            Timeline::Likes => "likes".to_string(),
+            Timeline::Bookmarks => "bookmarks".to_string(),
+            Timeline::UserMedia { user_id, .. } => format!("user_media:{}", user_id),
+        }
+    }
+
+    fn mock_file<'a>(&self, config: &'a Config) -> &'a str {
+        match self {
+            Timeline::Likes => &config.mock_liked_tweets_file,
+            Timeline::Bookmarks => &config.mock_bookmarks_file,
+            Timeline::UserMedia { .. } => &config.mock_user_media_file,
+        }
+    }
+}
+
+/// 包含令牌的请求头
+const SECRET_HEADERS: [HeaderName; 3] = [
+    AUTHORIZATION,
+    COOKIE,
+    HeaderName::from_static("x-csrf-token"),
+];
+
+/// 登录会话过期或无效导致的请求失败，需要重新运行 setup 更新令牌
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthError {
+    pub status: u16,
+    /// X 返回的错误码，如 32、89、353
+    pub code: Option<u64>,
+    pub message: String,
+}
+
+/// 表示登录会话失效的错误码：32 无法验证身份，89 令牌无效或已过期，353 ct0 与 cookie 不匹配
+const AUTH_ERROR_CODES: [u64; 3] = [32, 89, 353];
+
+impl AuthError {
+    /// 根据状态码和响应内容判断是否为会话失效：401 总是视为会话失效；
+    /// 403 也可能是账号受限或内容不可见，只有响应中带有上述错误码时才视为会话失效
+    pub fn classify(status: StatusCode, body: &str) -> Option<Self> {
+        let errors = serde_json::from_str::<Value>(body).ok();
+        let error = errors
+            .as_ref()
+            .and_then(|v| v.get("errors"))
+            .and_then(|e| e.as_array())
+            .into_iter()
+            .flatten()
+            .find(|e| {
+                e.get("code")
+                    .and_then(|c| c.as_u64())
+                    .is_some_and(|c| AUTH_ERROR_CODES.contains(&c))
+            });
+
+        let is_auth = match status {
+            StatusCode::UNAUTHORIZED => true,
+            StatusCode::FORBIDDEN => error.is_some(),
+            _ => false,
+        };
+        if !is_auth {
+            return None;
+        }
+
+        Some(AuthError {
+            status: status.as_u16(),
+            code: error.and_then(|e| e.get("code")).and_then(|c| c.as_u64()),
+            message: error
+                .and_then(|e| e.get("message"))
+                .and_then(|m| m.as_str())
+                .unwrap_or_else(|| status.canonical_reason().unwrap_or(""))
+                .to_string(),
+        })
+    }
+
+    /// 需要更新的令牌
+    pub fn stale_tokens(&self) -> &'static str {
+        match self.code {
+            // ct0 是 CSRF 令牌，与 cookie 中的值不一致时返回 353
+            Some(353) => "CT0",
+            _ => "AUTH_TOKEN 和 CT0",
+        }
+    }
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "登录会话已过期或无效 (HTTP {}", self.status)?;
+        if let Some(code) = self.code {
+            write!(f, "，错误码 {}", code)?;
+        }
+        write!(f, "): {}", self.message)?;
+        write!(
+            f,
+            "\n需要更新: {}。请在浏览器中重新登录 X，复制新的 cURL 命令后运行 `x_likes_downloader setup`",
+            self.stale_tokens()
+        )
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// 格式化请求头用于调试输出，认证相关的值替换为占位符
+pub fn redact_headers(headers: &HeaderMap) -> String {
+    let fields: Vec<String> = headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SECRET_HEADERS.contains(name) {
+                REDACTED
+            } else {
+                value.to_str().unwrap_or("<非文本>")
+            };
+            format!("{}: {}", name, value)
+        })
+        .collect();
+    format!("{{{}}}", fields.join(", "))
+}
+
+/// 从推文 URL（如 `https://x.com/user/status/123`）或纯数字 ID 中提取推文 ID
+pub fn parse_tweet_id(input: &str) -> Option<String> {
+    let input = input.trim();
+    if !input.is_empty() && input.chars().all(|c| c.is_ascii_digit()) {
+        return Some(input.to_string());
+    }
+
+    let url = Url::parse(input).ok()?;
+    let mut segments = url.path_segments()?;
+    segments.find(|s| *s == "status" || *s == "statuses")?;
+    segments
+        .next()
+        .filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+        .map(|id| id.to_string())
+}
+
+/// 增量模式的停止翻页条件
+pub struct IncrementalStop {
+    /// 连续遇到多少条已下载的推文后停止翻页，0 表示不按此条件停止
+    stop_after: usize,
+    /// 上次运行记录的最新 sortIndex，遇到不大于它的条目即停止翻页
+    newest_sort_index: Option<u128>,
+    /// 跨页累计的连续已下载条目数
+    consecutive_known: usize,
+}
+
+impl IncrementalStop {
+    pub fn new(stop_after: usize, newest_sort_index: Option<u128>) -> Self {
+        IncrementalStop {
+            stop_after,
+            newest_sort_index,
+            consecutive_known: 0,
+        }
+    }
+
+    /// 检查本页是否已到达之前下载过的位置，需在下载本页之前调用。
+    /// 没有可下载媒体的推文和不可用的推文不会被记录为已下载，遇到时不重新计数
+    pub fn reached_known(
+        &mut self,
+        entries: &[Entry],
+        is_downloaded: impl Fn(&str) -> bool,
+        has_media: impl Fn(&Tweet) -> bool,
+    ) -> bool {
+        for entry in entries {
+            if let (Some(sort_index), Some(newest)) = (entry.sort_index(), self.newest_sort_index) {
+                if sort_index <= newest {
+                    info!("增量模式：已到达上次记录的位置，停止翻页");
+                    return true;
+                }
+            }
+
+            match entry.tweet_id() {
+                Some(tweet_id) if is_downloaded(tweet_id) => {
+                    self.consecutive_known += 1;
+                    if self.stop_after > 0 && self.consecutive_known >= self.stop_after {
+                        info!(
+                            "增量模式：连续 {} 条推文已下载，停止翻页",
+                            self.consecutive_known
+                        );
+                        return true;
+                    }
+                }
+                Some(_) => {
+                    if let Some(TweetResult::Tweet(tweet)) = entry.tweet_result() {
+                        if has_media(tweet) {
+                            self.consecutive_known = 0;
+                        }
+                    }
+                }
+                None => {}
+            }
+        }
+        false
+    }
+}
+
+/// 时间线中的一页
+pub struct TimelinePage {
+    pub entries: Vec<Entry>,
+    /// 下一页的 cursor，为 None 表示时间线已到末尾
+    pub next_cursor: Option<String>,
+}
 
 #[derive(Debug)]
 pub struct XApi {
     client: Client,
     config: Config,
+    rate_limiter: RateLimiter,
 }
 
 impl XApi {
     pub fn new(config: Config) -> Result<Self> {
-        let client = reqwest::Client::builder().build()?;
+        let client = config.proxy.apply(reqwest::Client::builder())?.build()?;
 
-        Ok(XApi { client, config })
+        Ok(XApi {
+            client,
+            config,
+            rate_limiter: RateLimiter::default(),
+        })
     }
 
-    pub async fn get_liked_tweets_internal(&self) -> Result<Vec<Value>> {
-        let mut all_tweets = Vec::new();
-        let mut cursor: Option<String> = None;
+    /// 以流的形式逐页抓取时间线，`start_cursor` 用于从检查点恢复。
+    /// 未启用 ALL 模式时只返回第一页；请求失败时返回错误并结束。
+    pub fn timeline_pages<'a>(
+        &'a self,
+        timeline: &'a Timeline,
+        start_cursor: Option<&str>,
+    ) -> impl Stream<Item = Result<TimelinePage>> + 'a {
+        let cursor = start_cursor.map(|c| c.to_string());
 
-        loop {
-            let mut variables = json!({
-                "userId": self.config.user_id,
-                "count": self.config.count.parse::<i32>()?,
+        stream::unfold(Some(cursor), move |state| async move {
+            let cursor = state?;
+            let (tweets, new_cursor) = match self.fetch_page(timeline, cursor.as_deref()).await {
+                Ok(page) => page,
+                Err(e) => return Some((Err(e), None)),
+            };
+
+            info!(
+                "本页获取到 {} 条 tweet，cursor: {:?}",
+                tweets.len(),
+                new_cursor
+            );
+
+            // 如果没有新的 cursor 或新 cursor 与上一次相同，则认为没有更多数据
+            let next_cursor = new_cursor.filter(|c| Some(c) != cursor.as_ref());
+            // 如果未启用 ALL 模式，则只返回第一页数据
+            let next_state = match next_cursor {
+                Some(ref c) if self.config.all => Some(Some(c.clone())),
+                _ => None,
+            };
+
+            let page = TimelinePage {
+                entries: tweets,
+                next_cursor,
+            };
+            Some((Ok(page), next_state))
+        })
+    }
+
+    async fn fetch_page(
+        &self,
+        timeline: &Timeline,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<Entry>, Option<String>)> {
+        if self.config.mock_mode {
+            // 最后一页之后没有更多数据
+            return match self.load_mock_page(timeline, cursor)? {
+                Some(data) => self.parse_timeline_response(timeline, &data),
+                None => Ok((Vec::new(), None)),
+            };
+        }
+
+        let count = self.config.count.parse::<i32>()?;
+        let (api_url, mut variables, features, fieldtoggles) = match timeline {
+            Timeline::Likes => (
+                &self.config.likes_api_url,
+                json!({
+                    "userId": self.config.user_id,
+                    "count": count,
+                    "includePromotedContent": false,
+                    "withClientEventToken": false,
+                    "withBirdwatchNotes": false,
+                    "withVoice": true,
+                    "withV2Timeline": true
+                }),
+                &self.config.likes_features,
+                Some(&self.config.likes_fieldtoggles),
+            ),
+            Timeline::Bookmarks => (
+                &self.config.bookmarks_api_url,
+                json!({
+                    "count": count,
+                    "includePromotedContent": false
+                }),
+                &self.config.bookmarks_features,
+                None,
+            ),
+            // UserMedia 与 Likes 使用相同的 features 和 fieldToggles
+            Timeline::UserMedia { user_id, .. } => (
+                &self.config.user_media_api_url,
+                json!({
+                    "userId": user_id,
+                    "count": count,
+                    "includePromotedContent": false,
+                    "withClientEventToken": false,
+                    "withBirdwatchNotes": false,
+                    "withVoice": true,
+                    "withV2Timeline": true
+                }),
+                &self.config.likes_features,
+                Some(&self.config.likes_fieldtoggles),
+            ),
+        };
+
+        if let Some(cursor_val) = cursor {
+            variables["cursor"] = json!(cursor_val);
+        }
+
+        let url = Self::graphql_url(api_url, &variables, features, fieldtoggles)?;
+        let data = self.get_json(&url).await?;
+        self.parse_timeline_response(timeline, &data)
+    }
+
+    /// 通过 TweetDetail 接口获取单条推文，返回与时间线一致的 `tweet-<id>` 条目
+    pub async fn get_tweet_detail(&self, tweet_id: &str) -> Result<Option<Entry>> {
+        let pages = if self.config.mock_mode {
+            let filename = &self.config.mock_tweet_detail_file;
+            let content = fs::read_to_string(filename)
+                .with_context(|| format!("无法读取 Mock 数据文件: {}", filename))?;
+            match serde_json::from_str(&content)
+                .with_context(|| format!("Mock 数据文件不是合法的 JSON: {}", filename))?
+            {
+                Value::Array(pages) => pages,
+                page => vec![page],
+            }
+        } else {
+            let variables = json!({
+                "focalTweetId": tweet_id,
+                "with_rux_injections": false,
+                "rankingMode": "Relevance",
                 "includePromotedContent": false,
-                "withClientEventToken": false,
-                "withBirdwatchNotes": false,
-                "withVoice": true,
-                "withV2Timeline": true
+                "withCommunity": true,
+                "withQuickPromoteEligibilityTweetFields": true,
+                "withBirdwatchNotes": true,
+                "withVoice": true
             });
+            let url = Self::graphql_url(
+                &self.config.tweet_detail_api_url,
+                &variables,
+                &self.config.tweet_features,
+                Some(&self.config.tweet_fieldtoggles),
+            )?;
+            vec![self.get_json(&url).await?]
+        };
 
-            if let Some(ref cursor_val) = cursor {
-                variables["cursor"] = json!(cursor_val);
+        let entry_id = format!("tweet-{}", tweet_id);
+        for data in &pages {
+            let response = TweetDetailResponse::deserialize(data)
+                .context("无法解析 TweetDetail 响应，接口结构可能已变化")?;
+            let entry = response
+                .data
+                .threaded_conversation_with_injections_v2
+                .instructions
+                .into_iter()
+                .filter_map(|instruction| match instruction {
+                    Instruction::TimelineAddEntries { entries } => Some(entries),
+                    _ => None,
+                })
+                .flatten()
+                .find(|entry| entry.entry_id == entry_id);
+            if entry.is_some() {
+                return Ok(entry);
             }
+        }
 
-            let variables_str = serde_json::to_string(&variables)?;
-            let variables_encoded = urlencoding::encode(&variables_str);
-            let features_encoded = urlencoding::encode(&self.config.likes_features);
-            let fieldtoggles_encoded = urlencoding::encode(&self.config.likes_fieldtoggles);
-
-            let url = format!(
-                "{}?variables={}&features={}&fieldToggles={}",
-                self.config.likes_api_url,
-                variables_encoded,
-                features_encoded,
-                fieldtoggles_encoded
-            );
+        Ok(None)
+    }
 
-            println!("请求 URL: {}", url);
+    /// 请求一页点赞时间线，检查保存的令牌是否仍然有效
+    pub async fn check_auth(&self) -> Result<()> {
+        let missing: Vec<&str> = [
+            ("BEARER_TOKEN", &self.config.bearer_token),
+            ("AUTH_TOKEN", &self.config.auth_token),
+            ("CT0", &self.config.ct0),
+        ]
+        .into_iter()
+        .filter(|(_, value)| value.is_empty())
+        .map(|(name, _)| name)
+        .collect();
+        if !missing.is_empty() {
+            return Err(anyhow::anyhow!(
+                "缺少令牌: {}。请先运行 `x_likes_downloader setup`",
+                missing.join("、")
+            ));
+        }
 
-            let mut headers = reqwest::header::HeaderMap::new();
-            headers.insert(
-                "Authorization",
-                format!("Bearer {}", self.config.bearer_token).parse()?,
-            );
-            headers.insert(
-                "Cookie",
-                format!(
-                    "auth_token={}; ct0={}",
-                    self.config.auth_token, self.config.ct0
-                )
-                .parse()?,
-            );
-            headers.insert("X-Csrf-Token", self.config.ct0.parse()?);
-            headers.insert("User-Agent", self.config.user_agent.parse()?);
+        self.fetch_page(&Timeline::Likes, None).await.map(|_| ())
+    }
+
+    /// 通过 UserByScreenName 接口将用户名解析为用户 ID
+    pub async fn resolve_user_id(&self, screen_name: &str) -> Result<String> {
+        // Mock 模式下不访问网络，直接以用户名作为 ID
+        if self.config.mock_mode {
+            return Ok(screen_name.to_string());
+        }
+
+        let variables = json!({ "screen_name": screen_name });
+        let url = Self::graphql_url(
+            &self.config.user_by_screen_name_api_url,
+            &variables,
+            &self.config.user_features,
+            Some(&self.config.user_fieldtoggles),
+        )?;
+        let data = self.get_json(&url).await?;
+        let response = UserByScreenNameResponse::deserialize(&data)
+            .context("无法解析 UserByScreenName 响应，接口结构可能已变化")?;
+
+        response
+            .data
+            .user
+            .and_then(|u| u.result)
+            .and_then(|r| r.rest_id)
+            .ok_or_else(|| anyhow::anyhow!("找不到用户: @{}", screen_name))
+    }
+
+    fn graphql_url(
+        api_url: &str,
+        variables: &Value,
+        features: &str,
+        fieldtoggles: Option<&String>,
+    ) -> Result<String> {
+        let variables_str = serde_json::to_string(variables)?;
+        let mut url = format!(
+            "{}?variables={}&features={}",
+            api_url,
+            urlencoding::encode(&variables_str),
+            urlencoding::encode(features)
+        );
+        if let Some(fieldtoggles) = fieldtoggles {
+            url.push_str("&fieldToggles=");
+            url.push_str(&urlencoding::encode(fieldtoggles));
+        }
+        Ok(url)
+    }
+
+    async fn get_json(&self, url: &str) -> Result<Value> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            "Authorization",
+            format!("Bearer {}", self.config.bearer_token).parse()?,
+        );
+        headers.insert(
+            "Cookie",
+            format!(
+                "auth_token={}; ct0={}",
+                self.config.auth_token, self.config.ct0
+            )
+            .parse()?,
+        );
+        headers.insert("X-Csrf-Token", self.config.ct0.parse()?);
+        headers.insert("User-Agent", self.config.user_agent.parse()?);
+
+        debug!("请求 URL: {}", url);
+        debug!("请求 headers: {}", redact_headers(&headers));
+
+        // 额度按接口分别计算
+        let endpoint = url.split('?').next().unwrap_or(url);
+        let max_retries = self.config.max_retries;
+        let mut attempt = 0;
 
-            println!("请求 headers: {:?}", headers);
+        loop {
+            self.rate_limiter.wait_for_budget(endpoint).await;
+
+            let response = match self.client.get(url).headers(headers.clone()).send().await {
+                Ok(response) => response,
+                Err(e) if attempt < max_retries && (e.is_timeout() || e.is_connect()) => {
+                    attempt += 1;
+                    let delay = self.rate_limiter.retry_delay(endpoint, None, attempt);
+                    let reason = format!("请求失败 ({})，第 {}/{} 次重试", e, attempt, max_retries);
+                    rate_limit::countdown(delay, &reason).await;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
 
-            let response = self.client.get(&url).headers(headers).send().await?;
+            self.rate_limiter.update(endpoint, response.headers());
 
-            if !response.status().is_success() {
-                let status = response.status();
-                let text = response.text().await?;
-                return Err(anyhow::anyhow!("API 请求失败: {} {}", status, text));
+            let status = response.status();
+            if status.is_success() {
+                return Ok(response.json().await?);
             }
 
-            let data: Value = response.json().await?;
+            if rate_limit::is_retryable_status(status) && attempt < max_retries {
+                attempt += 1;
+                let delay = self
+                    .rate_limiter
+                    .retry_delay(endpoint, Some(status), attempt);
+                let reason = format!("API 返回 {}，第 {}/{} 次重试", status, attempt, max_retries);
+                rate_limit::countdown(delay, &reason).await;
+                continue;
+            }
 
-            let (tweets, new_cursor) = self.parse_likes_response(&data)?;
-            println!(
-                "本页获取到 {} 条 tweet，cursor: {:?}",
-                tweets.len(),
-                new_cursor
-            );
+            let text = response.text().await?;
+            if let Some(auth_error) = AuthError::classify(status, &text) {
+                return Err(auth_error.into());
+            }
+            return Err(anyhow::anyhow!("API 请求失败: {} {}", status, text));
+        }
+    }
 
-            all_tweets.extend(tweets);
+    /// 从 Mock 数据文件中读取一页响应，最后一页之后返回 None
+    ///
+    /// 文件可以是单个响应对象，也可以是响应对象数组（多页）。
+    /// 多页时按 cursor 串联：请求某个 cursor 时，返回 bottom cursor 等于它的那一页的下一页。
+    fn load_mock_page(&self, timeline: &Timeline, cursor: Option<&str>) -> Result<Option<Value>> {
+        let filename = timeline.mock_file(&self.config);
+        let content = fs::read_to_string(filename)
+            .with_context(|| format!("无法读取 Mock 数据文件: {}", filename))?;
+        let fixture: Value = serde_json::from_str(&content)
+            .with_context(|| format!("Mock 数据文件不是合法的 JSON: {}", filename))?;
 
-            // 如果未启用 ALL 模式，则只返回第一页数据
-            if !self.config.all {
-                break;
+        let pages = match fixture {
+            Value::Array(pages) => pages,
+            page => vec![page],
+        };
+
+        let cursor = match cursor {
+            Some(cursor) => cursor,
+            None => {
+                return match pages.into_iter().next() {
+                    Some(page) => Ok(Some(page)),
+                    None => Err(anyhow::anyhow!("Mock 数据文件中没有任何页: {}", filename)),
+                }
             }
+        };
 
-            // 如果没有新的 cursor 或新 cursor 与上一次相同，则认为没有更多数据
-            if new_cursor.is_none() || new_cursor == cursor {
-                break;
+        for (i, page) in pages.iter().enumerate() {
+            let (_, page_cursor) = self.parse_timeline_response(timeline, page)?;
+            if page_cursor.as_deref() == Some(cursor) {
+                return Ok(pages.get(i + 1).cloned());
             }
-            cursor = new_cursor;
         }
 
-        Ok(all_tweets)
+        Err(anyhow::anyhow!(
+            "Mock 数据文件中找不到 cursor 对应的页: {}",
+            cursor
+        ))
     }
 
-    fn parse_likes_response(&self, data: &Value) -> Result<(Vec<Value>, Option<String>)> {
+    /// 解析时间线响应，返回推文条目和 bottom cursor。接口结构变化时返回指出缺失字段的错误
+    fn parse_timeline_response(
+        &self,
+        timeline: &Timeline,
+        data: &Value,
+    ) -> Result<(Vec<Entry>, Option<String>)> {
+        let parsed = match timeline {
+            Timeline::Likes | Timeline::UserMedia { .. } => UserTimelineResponse::deserialize(data)
+                .map(|r| r.data.user.result.timeline_v2.timeline),
+            Timeline::Bookmarks => {
+                BookmarksResponse::deserialize(data).map(|r| r.data.bookmark_timeline_v2.timeline)
+            }
+        }
+        .with_context(|| format!("无法解析{}时间线响应，接口结构可能已变化", timeline.label()))?;
+
         let mut tweets = Vec::new();
         let mut new_cursor = None;
 
-        if let Some(instructions) = data
-            .get("data")
-            .and_then(|d| d.get("user"))
-            .and_then(|u| u.get("result"))
-            .and_then(|r| r.get("timeline_v2"))
-            .and_then(|t| t.get("timeline"))
-            .and_then(|t| t.get("instructions"))
-            .and_then(|i| i.as_array())
-        {
-            for instruction in instructions {
-                if instruction.get("type") == Some(&json!("TimelineAddEntries")) {
-                    if let Some(entries) = instruction.get("entries").and_then(|e| e.as_array()) {
-                        for entry in entries {
-                            if let Some(entry_id) = entry.get("entryId").and_then(|id| id.as_str())
-                            {
-                                if entry_id.starts_with("tweet-") {
-                                    tweets.push(entry.clone());
-                                } else if entry_id.starts_with("cursor-bottom-") {
-                                    new_cursor = entry
-                                        .get("content")
-                                        .and_then(|c| c.get("value"))
-                                        .and_then(|v| v.as_str())
-                                        .map(|s| s.to_string());
-                                }
-                            }
+        for instruction in parsed.instructions {
+            match instruction {
+                Instruction::TimelineAddEntries { entries } => {
+                    for entry in entries {
+                        if entry.is_tweet() {
+                            tweets.push(entry);
+                        } else if let Some(cursor) = entry.bottom_cursor() {
+                            new_cursor = Some(cursor.to_string());
+                        } else if let Some(items) = entry.content.items {
+                            // UserMedia 首页的媒体网格以模块形式返回
+                            tweets.extend(items.into_iter().filter_map(ModuleItem::into_entry));
                         }
                     }
                 }
+                Instruction::TimelineAddToModule { module_items } => {
+                    // UserMedia 后续页向已有网格模块追加条目
+                    tweets.extend(module_items.into_iter().filter_map(ModuleItem::into_entry));
+                }
+                Instruction::Other => {}
+            }
+        }
+
+        // 条目逐个解析，格式异常的只跳过；整页的推文都无法解析时说明接口结构已变化
+        if tweets.is_empty() {
+            let mut count = 0;
+            let mut first_error = None;
+            unparsed_tweet_entries(data, &mut count, &mut first_error);
+            if let Some(error) = first_error {
+                return Err(anyhow::anyhow!(
+                    "{}时间线的 {} 条推文都无法解析，接口结构可能已变化: {}",
+                    timeline.label(),
+                    count,
+                    error
+                ));
             }
         }
 
         Ok((tweets, new_cursor))
     }
 }
+
+/// 统计响应中的推文条目，记录第一个条目的解析错误（其中指出缺失的字段）
+fn unparsed_tweet_entries(value: &Value, count: &mut usize, first_error: &mut Option<String>) {
+    match value {
+        Value::Object(map) => {
+            let entry_id = map.get("entryId").and_then(Value::as_str).unwrap_or("");
+            let error = if entry_id.starts_with("tweet-") {
+                Entry::deserialize(value).err()
+            } else if entry_id.contains("-tweet-") {
+                ModuleItem::deserialize(value).err()
+            } else {
+                map.values()
+                    .for_each(|v| unparsed_tweet_entries(v, count, first_error));
+                return;
+            };
+            *count += 1;
+            if first_error.is_none() {
+                *first_error = error.map(|e| format!("{} ({})", e, entry_id));
+            }
+        }
+        Value::Array(values) => values
+            .iter()
+            .for_each(|v| unparsed_tweet_entries(v, count, first_error)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+#[path = "x_api_test.rs"]
+mod tests;